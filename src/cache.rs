@@ -0,0 +1,275 @@
+//! A cache of evaluated projects, stored in the repository so that later runs only evaluate
+//! the projects whose files changed.
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    project::Project,
+    properties::{solution_dir, BuildContext},
+    repo, Error, Result,
+};
+
+/// The directory the cache lives in, relative to the repository root.
+pub const CACHE_DIRECTORY: &str = ".rami-api-deps";
+const CACHE_FILE: &str = "cache.json";
+/// Bumped when the cached data changes without a new release of the tool.
+const CACHE_FORMAT: u32 = 4;
+
+/// Files MSBuild and NuGet pick up without the project importing them explicitly. When one of
+/// them appears or disappears, cached evaluations below it are stale.
+const IMPLICIT_FILES: [&str; 7] = [
+    "Directory.Build.props",
+    "Directory.Build.targets",
+    "Directory.Packages.props",
+    "NuGet.config",
+    "NuGet.Config",
+    "nuget.config",
+    "global.json",
+];
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Cache {
+    /// The version of the tool and cache format that wrote the cache. Entries of other versions
+    /// are discarded, since the evaluation may have changed.
+    version: String,
+    /// Keyed by the configuration, platform, `SolutionDir` and path of the project.
+    entries: BTreeMap<String, Entry>,
+    #[serde(skip)]
+    modified: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Entry {
+    /// Content hashes of the project file and every file it imports, `None` for imports that
+    /// did not exist.
+    inputs: BTreeMap<PathBuf, Option<u64>>,
+    /// The implicitly imported files that existed when the project was evaluated.
+    implicit: Vec<PathBuf>,
+    /// The values of the environment variables the project's files refer to as properties,
+    /// since properties fall back to environment variables.
+    environment: BTreeMap<String, Option<String>>,
+    project: Project,
+}
+
+impl Cache {
+    pub fn path(repo_root: &Path) -> PathBuf {
+        repo_root.join(CACHE_DIRECTORY).join(CACHE_FILE)
+    }
+
+    /// Loads the cache of the repository. A missing, unreadable or outdated cache is treated as
+    /// empty.
+    pub fn load(repo_root: &Path) -> Self {
+        let cache = std::fs::read_to_string(Self::path(repo_root))
+            .ok()
+            .and_then(|contents| serde_json::from_str::<Cache>(&contents).ok())
+            .filter(|cache| cache.version == version());
+
+        cache.unwrap_or_else(|| Cache {
+            version: version(),
+            ..Cache::default()
+        })
+    }
+
+    /// Writes the cache if anything was added since it was loaded. The cache directory ignores
+    /// itself, so it never shows up in `git status`.
+    pub fn save(&self, repo_root: &Path) -> Result<()> {
+        if !self.modified {
+            return Ok(());
+        }
+
+        let directory = repo_root.join(CACHE_DIRECTORY);
+        std::fs::create_dir_all(&directory).map_err(|e| Error::io(&directory, e))?;
+        let gitignore = directory.join(".gitignore");
+        std::fs::write(&gitignore, "*\n").map_err(|e| Error::io(&gitignore, e))?;
+
+        let path = Self::path(repo_root);
+        let contents = serde_json::to_string(self).map_err(|e| Error::io(&path, e.into()))?;
+        std::fs::write(&path, contents).map_err(|e| Error::io(&path, e))
+    }
+
+    /// Deletes the cache of the repository.
+    pub fn clear(repo_root: &Path) -> Result<()> {
+        let directory = repo_root.join(CACHE_DIRECTORY);
+        match std::fs::remove_dir_all(&directory) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(Error::io(&directory, e)),
+            _ => Ok(()),
+        }
+    }
+
+    /// The cached evaluation of the project at `path`, if none of its files changed since.
+    pub fn get(&self, repo_root: &Path, context: &BuildContext, path: &Path) -> Option<Project> {
+        let entry = self.entries.get(&key(repo_root, context, path))?;
+        let unchanged = entry
+            .inputs
+            .iter()
+            .all(|(input, hash)| hash_file(repo_root, input) == *hash)
+            && entry.implicit == implicit_files(repo_root, path)
+            && entry
+                .environment
+                .iter()
+                .all(|(name, value)| environment_variable(name) == *value);
+
+        unchanged.then(|| entry.project.clone())
+    }
+
+    pub fn insert(&mut self, repo_root: &Path, context: &BuildContext, project: &Project) {
+        let inputs = std::iter::once(&project.path)
+            .chain(&project.imports)
+            .chain(&project.package_files)
+            .chain(&project.missing_imports)
+            .map(|input| (input.clone(), hash_file(repo_root, input)))
+            .collect::<BTreeMap<_, _>>();
+        let environment = inputs
+            .keys()
+            .filter_map(|input| std::fs::read_to_string(repo_root.join(input)).ok())
+            .flat_map(|contents| property_references(&contents))
+            .map(|name| {
+                let value = environment_variable(&name);
+                (name, value)
+            })
+            .collect();
+
+        self.entries.insert(
+            key(repo_root, context, &project.path),
+            Entry {
+                inputs,
+                implicit: implicit_files(repo_root, &project.path),
+                environment,
+                project: project.clone(),
+            },
+        );
+        self.modified = true;
+    }
+}
+
+fn version() -> String {
+    format!("{}+{}", env!("CARGO_PKG_VERSION"), CACHE_FORMAT)
+}
+
+fn key(repo_root: &Path, context: &BuildContext, path: &Path) -> String {
+    format!(
+        "{}|{}|{}|{}",
+        context.configuration,
+        context.platform,
+        repo::azure_path(&solution_dir(repo_root, path)),
+        repo::azure_path(path)
+    )
+}
+
+/// The names of the properties referenced as `$(Name)` in `contents`, which may be read from
+/// environment variables. Property functions and item references are skipped.
+fn property_references(contents: &str) -> Vec<String> {
+    contents
+        .split("$(")
+        .skip(1)
+        .filter_map(|reference| reference.split_once(')'))
+        .map(|(name, _)| name.trim())
+        .filter(|name| {
+            !name.is_empty()
+                && name
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+        })
+        .map(str::to_string)
+        .collect()
+}
+
+/// Environment variables are looked up case-insensitively, like properties.
+fn environment_variable(name: &str) -> Option<String> {
+    std::env::vars_os()
+        .find(|(key, _)| {
+            key.to_str()
+                .is_some_and(|key| key.eq_ignore_ascii_case(name))
+        })
+        .map(|(_, value)| value.to_string_lossy().into_owned())
+}
+
+fn implicit_files(repo_root: &Path, project_path: &Path) -> Vec<PathBuf> {
+    let project_dir = project_path.parent().unwrap_or(Path::new(""));
+    project_dir
+        .ancestors()
+        .flat_map(|directory| IMPLICIT_FILES.iter().map(|name| directory.join(name)))
+        .filter(|path| repo_root.join(path).is_file())
+        .collect()
+}
+
+/// The 64-bit FNV-1a hash of the file contents. Not cryptographic, but plenty to notice edits.
+fn hash_file(repo_root: &Path, path: &Path) -> Option<u64> {
+    let contents = std::fs::read(repo_root.join(path)).ok()?;
+    Some(contents.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x100000001b3)
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::write_tree;
+
+    #[test]
+    fn test_invalidation() {
+        let dir = tempfile::tempdir().unwrap();
+        write_tree(
+            dir.path(),
+            &[(
+                "Api/Api.csproj",
+                r#"<Project>
+  <Import Project="..\Shared.props" Condition="Exists('..\Shared.props')" />
+  <ItemGroup>
+    <ProjectReference Include="$(SolutionDir)$(RAMI_API_DEPS_CACHE_TEST)\Shared.csproj" Condition="'$(RAMI_API_DEPS_CACHE_TEST)' != ''" />
+  </ItemGroup>
+</Project>"#,
+            )],
+        );
+        let context = BuildContext::default();
+        let path = Path::new("Api/Api.csproj");
+
+        let mut cache = Cache::load(dir.path());
+        let project = Project::new(dir.path(), path.to_path_buf(), &context).unwrap();
+        cache.insert(dir.path(), &context, &project);
+        cache.save(dir.path()).unwrap();
+
+        let cache = Cache::load(dir.path());
+        assert!(cache.get(dir.path(), &context, path).is_some());
+        let debug = BuildContext {
+            configuration: "Debug".to_string(),
+            ..BuildContext::default()
+        };
+        assert!(cache.get(dir.path(), &debug, path).is_none());
+
+        write_tree(dir.path(), &[("Directory.Build.props", "<Project />")]);
+        assert!(cache.get(dir.path(), &context, path).is_none());
+        std::fs::remove_file(dir.path().join("Directory.Build.props")).unwrap();
+        assert!(cache.get(dir.path(), &context, path).is_some());
+
+        write_tree(dir.path(), &[("Shared.props", "<Project />")]);
+        assert!(cache.get(dir.path(), &context, path).is_none());
+        std::fs::remove_file(dir.path().join("Shared.props")).unwrap();
+        assert!(cache.get(dir.path(), &context, path).is_some());
+
+        // References and conditions can depend on the environment and the solution.
+        std::env::set_var("RAMI_API_DEPS_CACHE_TEST", "Shared");
+        assert!(cache.get(dir.path(), &context, path).is_none());
+        std::env::remove_var("RAMI_API_DEPS_CACHE_TEST");
+        assert!(cache.get(dir.path(), &context, path).is_some());
+
+        write_tree(dir.path(), &[("Api/Api.slnx", "<Solution />")]);
+        assert!(cache.get(dir.path(), &context, path).is_none());
+        std::fs::remove_file(dir.path().join("Api/Api.slnx")).unwrap();
+        assert!(cache.get(dir.path(), &context, path).is_some());
+
+        write_tree(
+            dir.path(),
+            &[("Api/Api.csproj", "<Project Sdk=\"Microsoft.NET.Sdk\" />")],
+        );
+        assert!(cache.get(dir.path(), &context, path).is_none());
+
+        Cache::clear(dir.path()).unwrap();
+        assert!(!Cache::path(dir.path()).exists());
+    }
+}
@@ -0,0 +1,266 @@
+//! Evaluation of MSBuild `Condition` attributes.
+//!
+//! Supports comparisons (`==`, `!=`, `<`, `>`, `<=`, `>=`), `And`, `Or`, `!`, parentheses,
+//! property references and the `Exists` and `HasTrailingSlash` functions. Anything else is
+//! unknown, and stays unknown when negated or combined with other unknown parts. Conditions that
+//! are unknown or cannot be parsed evaluate to `true`, so that a path filter errs on the side of
+//! including too much.
+
+use nom::{
+    branch::alt,
+    bytes::complete::{tag, tag_no_case, take_until, take_while1},
+    character::complete::{char, multispace0, satisfy},
+    combinator::{all_consuming, map, not, opt, recognize, value},
+    multi::{many0, separated_list0},
+    sequence::{delimited, pair, preceded, terminated, tuple},
+    IResult,
+};
+
+use crate::properties::Properties;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Expression {
+    Or(Box<Expression>, Box<Expression>),
+    And(Box<Expression>, Box<Expression>),
+    Not(Box<Expression>),
+    Compare(String, Operator, String),
+    Function(String, Vec<String>),
+    Operand(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Operator {
+    Equal,
+    NotEqual,
+    LessThan,
+    GreaterThan,
+    LessOrEqual,
+    GreaterOrEqual,
+}
+
+/// Evaluates `condition` with the given properties. `exists` decides whether a path passed to
+/// `Exists()` is present, relative paths are relative to the file the condition is in.
+pub fn evaluate(condition: &str, properties: &Properties, exists: &dyn Fn(&str) -> bool) -> bool {
+    if condition.trim().is_empty() {
+        return true;
+    }
+
+    match all_consuming(delimited(multispace0, or_expression, multispace0))(condition) {
+        Ok((_, expression)) => evaluate_expression(&expression, properties, exists).unwrap_or(true),
+        Err(_) => true,
+    }
+}
+
+/// `None` if the result is unknown. `And` and `Or` still decide when one side settles the
+/// result, e.g. `false And Unknown()` is `false`.
+fn evaluate_expression(
+    expression: &Expression,
+    properties: &Properties,
+    exists: &dyn Fn(&str) -> bool,
+) -> Option<bool> {
+    match expression {
+        Expression::Or(left, right) => match (
+            evaluate_expression(left, properties, exists),
+            evaluate_expression(right, properties, exists),
+        ) {
+            (Some(true), _) | (_, Some(true)) => Some(true),
+            (Some(false), Some(false)) => Some(false),
+            _ => None,
+        },
+        Expression::And(left, right) => match (
+            evaluate_expression(left, properties, exists),
+            evaluate_expression(right, properties, exists),
+        ) {
+            (Some(false), _) | (_, Some(false)) => Some(false),
+            (Some(true), Some(true)) => Some(true),
+            _ => None,
+        },
+        Expression::Not(inner) => {
+            evaluate_expression(inner, properties, exists).map(|value| !value)
+        }
+        Expression::Compare(left, operator, right) => compare(
+            &properties.expand(left),
+            *operator,
+            &properties.expand(right),
+        ),
+        Expression::Function(name, arguments) => {
+            let argument = arguments
+                .first()
+                .map(|argument| properties.expand(argument))
+                .unwrap_or_default();
+            match name.to_lowercase().as_str() {
+                "exists" => Some(!argument.trim().is_empty() && exists(argument.trim())),
+                "hastrailingslash" => Some(argument.ends_with(['/', '\\'])),
+                _ => None,
+            }
+        }
+        Expression::Operand(operand) => {
+            let operand = properties.expand(operand);
+            // A property function that could not be evaluated.
+            if operand.contains("$([") {
+                return None;
+            }
+            Some(!operand.eq_ignore_ascii_case("false"))
+        }
+    }
+}
+
+fn compare(left: &str, operator: Operator, right: &str) -> Option<bool> {
+    match operator {
+        Operator::Equal => Some(left.eq_ignore_ascii_case(right)),
+        Operator::NotEqual => Some(!left.eq_ignore_ascii_case(right)),
+        _ => {
+            let (Ok(left), Ok(right)) = (left.trim().parse::<f64>(), right.trim().parse::<f64>())
+            else {
+                return None;
+            };
+            Some(match operator {
+                Operator::LessThan => left < right,
+                Operator::GreaterThan => left > right,
+                Operator::LessOrEqual => left <= right,
+                _ => left >= right,
+            })
+        }
+    }
+}
+
+fn keyword<'a>(word: &'static str) -> impl FnMut(&'a str) -> IResult<&'a str, &'a str> {
+    delimited(
+        multispace0,
+        terminated(
+            tag_no_case(word),
+            not(satisfy(|c: char| c.is_alphanumeric())),
+        ),
+        multispace0,
+    )
+}
+
+fn or_expression(input: &str) -> IResult<&str, Expression> {
+    let (input, first) = and_expression(input)?;
+    let (input, rest) = many0(preceded(keyword("or"), and_expression))(input)?;
+
+    Ok((
+        input,
+        rest.into_iter().fold(first, |left, right| {
+            Expression::Or(Box::new(left), Box::new(right))
+        }),
+    ))
+}
+
+fn and_expression(input: &str) -> IResult<&str, Expression> {
+    let (input, first) = unary_expression(input)?;
+    let (input, rest) = many0(preceded(keyword("and"), unary_expression))(input)?;
+
+    Ok((
+        input,
+        rest.into_iter().fold(first, |left, right| {
+            Expression::And(Box::new(left), Box::new(right))
+        }),
+    ))
+}
+
+fn unary_expression(input: &str) -> IResult<&str, Expression> {
+    let (input, _) = multispace0(input)?;
+    alt((
+        map(preceded(char('!'), unary_expression), |inner| {
+            Expression::Not(Box::new(inner))
+        }),
+        delimited(
+            char('('),
+            delimited(multispace0, or_expression, multispace0),
+            char(')'),
+        ),
+        function,
+        comparison,
+        map(operand, Expression::Operand),
+    ))(input)
+}
+
+fn function(input: &str) -> IResult<&str, Expression> {
+    map(
+        pair(
+            word,
+            delimited(
+                pair(multispace0, char('(')),
+                separated_list0(char(','), delimited(multispace0, operand, multispace0)),
+                char(')'),
+            ),
+        ),
+        |(name, arguments)| Expression::Function(name.to_string(), arguments),
+    )(input)
+}
+
+fn comparison(input: &str) -> IResult<&str, Expression> {
+    map(
+        tuple((
+            operand,
+            delimited(multispace0, operator, multispace0),
+            operand,
+        )),
+        |(left, operator, right)| Expression::Compare(left, operator, right),
+    )(input)
+}
+
+fn operator(input: &str) -> IResult<&str, Operator> {
+    alt((
+        value(Operator::Equal, tag("==")),
+        value(Operator::NotEqual, tag("!=")),
+        value(Operator::LessOrEqual, tag("<=")),
+        value(Operator::GreaterOrEqual, tag(">=")),
+        value(Operator::LessThan, tag("<")),
+        value(Operator::GreaterThan, tag(">")),
+    ))(input)
+}
+
+/// A quoted string, a property reference or a bare word such as `true` or a number.
+fn operand(input: &str) -> IResult<&str, String> {
+    map(
+        alt((
+            delimited(char('\''), recognize(opt(take_until("'"))), char('\'')),
+            recognize(tuple((tag("$("), take_until(")"), tag(")")))),
+            word,
+        )),
+        str::to_string,
+    )(input)
+}
+
+fn word(input: &str) -> IResult<&str, &str> {
+    take_while1(|c: char| c.is_alphanumeric() || matches!(c, '.' | '_' | '-'))(input)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::properties::BuildContext;
+
+    #[test]
+    fn test_evaluate() {
+        let properties = BuildContext::default().properties();
+        let exists = |path: &str| path == r"..\Shared\Shared.csproj";
+        let evaluate = |condition: &str| evaluate(condition, &properties, &exists);
+
+        assert!(evaluate("'$(Configuration)' == 'Release'"));
+        assert!(!evaluate(
+            "'$(Configuration)|$(Platform)' == 'Debug|AnyCPU'"
+        ));
+        assert!(evaluate(
+            "'$(Configuration)' != 'Debug' And '$(Platform)' == 'anycpu'"
+        ));
+        assert!(evaluate(
+            "('$(Configuration)' == 'Debug' or $(Platform) == 'AnyCPU') and !false"
+        ));
+        assert!(evaluate(r"Exists('..\Shared\Shared.csproj')"));
+        assert!(!evaluate(r"!Exists('..\Shared\Shared.csproj')"));
+        assert!(!evaluate("'$(UndefinedProperty)' != ''"));
+        assert!(evaluate("'$(TargetFramework)' >= '6.0'"));
+        assert!(evaluate("$([MSBuild]::IsOSPlatform('Windows'))"));
+
+        // Unknown parts stay unknown when negated, and only decide the result when they could.
+        assert!(evaluate("!Unknown()"));
+        assert!(evaluate("!$([MSBuild]::IsOSPlatform('Windows'))"));
+        assert!(evaluate("!('$(TargetFramework)' >= '6.0')"));
+        assert!(evaluate("!Unknown() And '$(Configuration)' == 'Release'"));
+        assert!(!evaluate("!Unknown() And '$(Configuration)' == 'Debug'"));
+        assert!(evaluate("!Unknown() Or '$(Configuration)' == 'Debug'"));
+    }
+}
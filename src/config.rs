@@ -0,0 +1,302 @@
+//! The repository configuration in `rami-deps.toml`, for what cannot be derived from the
+//! pipelines and projects, e.g. a frontend that is built next to the projects.
+//!
+//! ```toml
+//! [[pipeline]]
+//! name = "CustomerPortal"        # a pipeline name or a glob, e.g. "*Module"
+//! include = ["/CustomerPortal/*"]
+//! exclude = ["/CustomerPortal/apis/modules/*"]
+//! ignore_projects = ["**/Legacy/*.csproj"]
+//!
+//! [[pipeline]]
+//! path = "ci/build.yml"          # matches the pipeline file instead, and names it
+//! name = "Api"
+//!
+//! [discovery]
+//! pipelines = ["**/azure-pipelines.yml", "ci/*.yml"]  # replaces the default patterns
+//! exclude = ["tools/**"]  # skipped on top of .gitignore and build output like bin and obj
+//!
+//! [tests]
+//! patterns = ["**/*.Specs.csproj"]  # replaces the default patterns
+//! include = false
+//! ```
+
+use std::path::Path;
+
+use globset::Glob;
+use serde::{Deserialize, Serialize};
+
+use crate::{Error, Result};
+
+pub const CONFIG_FILE_NAME: &str = "rami-deps.toml";
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    #[serde(default, rename = "pipeline")]
+    pub pipelines: Vec<PipelineConfig>,
+    #[serde(default)]
+    pub tests: TestSettings,
+    #[serde(default)]
+    pub discovery: DiscoverySettings,
+}
+
+/// Which files are pipelines, and which paths are not searched at all.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DiscoverySettings {
+    /// Globs of pipeline files relative to the repository root.
+    pub pipelines: Vec<String>,
+    /// Globs of files and directories relative to the repository root that are skipped.
+    pub exclude: Vec<String>,
+}
+
+impl Default for DiscoverySettings {
+    fn default() -> Self {
+        Self {
+            pipelines: [
+                "**/azure-pipelines.yml",
+                "**/azure-pipelines.yaml",
+                "**/*.azure-pipelines.yml",
+                "**/*.azure-pipelines.yaml",
+            ]
+            .map(str::to_string)
+            .to_vec(),
+            exclude: Vec::new(),
+        }
+    }
+}
+
+/// How test projects are recognized, besides `IsTestProject` and test framework packages, and
+/// whether pipelines include them.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TestSettings {
+    /// Globs of project files relative to the repository root that are test projects.
+    pub patterns: Vec<String>,
+    /// Keep test projects in pipelines and their path filters instead of leaving them out.
+    pub include: bool,
+}
+
+impl Default for TestSettings {
+    fn default() -> Self {
+        Self {
+            patterns: vec![
+                "**/*.*Tests.csproj".to_string(),
+                "**/*.Test.csproj".to_string(),
+            ],
+            include: false,
+        }
+    }
+}
+
+/// Settings for the pipelines whose name matches `name`, see [`PipelineSettings`]. With
+/// `path`, the entry matches the pipeline files at `path` instead and `name` names them.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PipelineConfig {
+    pub name: Option<String>,
+    pub path: Option<String>,
+    #[serde(default)]
+    pub include: Vec<String>,
+    #[serde(default)]
+    pub exclude: Vec<String>,
+    #[serde(default)]
+    pub ignore_projects: Vec<String>,
+}
+
+/// What is added to or removed from a pipeline on top of what its projects lead to.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PipelineSettings {
+    /// Paths added to the path filter, written like in it, e.g. `/CustomerPortal/*`.
+    pub include: Vec<String>,
+    /// Paths excluded by the path filter.
+    pub exclude: Vec<String>,
+    /// Globs of project files relative to the repository root, e.g. `**/Legacy/*.csproj`.
+    /// Matching projects are left out of the pipeline and its path filter.
+    pub ignore_projects: Vec<String>,
+}
+
+impl Config {
+    /// Loads the configuration from the repository root. Without a config file, nothing is
+    /// configured.
+    pub fn load(repo_root: &Path) -> Result<Self> {
+        let path = Path::new(CONFIG_FILE_NAME);
+        let contents = match std::fs::read_to_string(repo_root.join(path)) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(Error::io(path, e)),
+        };
+
+        Self::parse(&contents)
+    }
+
+    pub fn parse(contents: &str) -> Result<Self> {
+        let path = Path::new(CONFIG_FILE_NAME);
+        let config = toml::from_str::<Config>(contents).map_err(|e| Error::ParseError {
+            file: path.to_path_buf(),
+            line: e
+                .span()
+                .map_or(0, |span| contents[..span.start].matches('\n').count() + 1),
+            message: e.message().to_string(),
+        })?;
+
+        if config
+            .pipelines
+            .iter()
+            .any(|pipeline| pipeline.name.is_none() && pipeline.path.is_none())
+        {
+            return Err(Error::ParseError {
+                file: path.to_path_buf(),
+                line: 0,
+                message: "every [[pipeline]] needs a `name` or a `path`".to_string(),
+            });
+        }
+
+        // Report invalid globs once here rather than ignoring them later.
+        let patterns = config
+            .pipelines
+            .iter()
+            .flat_map(|pipeline| {
+                pipeline
+                    .name
+                    .iter()
+                    .chain(&pipeline.path)
+                    .chain(&pipeline.ignore_projects)
+            })
+            .chain(&config.tests.patterns)
+            .chain(&config.discovery.pipelines)
+            .chain(&config.discovery.exclude);
+        for pattern in patterns {
+            Glob::new(pattern).map_err(|e| Error::ParseError {
+                file: path.to_path_buf(),
+                line: 0,
+                message: e.to_string(),
+            })?;
+        }
+
+        Ok(config)
+    }
+
+    /// The name configured for the pipeline file at `path`, relative to the repository root.
+    pub fn pipeline_name(&self, path: &Path) -> Option<&str> {
+        self.pipelines
+            .iter()
+            .filter(|pipeline| {
+                pipeline
+                    .path
+                    .as_ref()
+                    .is_some_and(|pattern| matches_any(std::slice::from_ref(pattern), path))
+            })
+            .find_map(|pipeline| pipeline.name.as_deref())
+    }
+
+    /// The settings of every entry matching the pipeline called `name` at `path`, combined.
+    pub fn pipeline(&self, name: &str, path: &Path) -> PipelineSettings {
+        let mut settings = PipelineSettings::default();
+        for pipeline in &self.pipelines {
+            let matches = match (&pipeline.path, &pipeline.name) {
+                (Some(pattern), _) => matches_any(std::slice::from_ref(pattern), path),
+                (None, Some(pattern)) => {
+                    Glob::new(pattern).is_ok_and(|glob| glob.compile_matcher().is_match(name))
+                }
+                (None, None) => false,
+            };
+            if matches {
+                settings.include.extend(pipeline.include.iter().cloned());
+                settings.exclude.extend(pipeline.exclude.iter().cloned());
+                settings
+                    .ignore_projects
+                    .extend(pipeline.ignore_projects.iter().cloned());
+            }
+        }
+
+        settings
+    }
+}
+
+impl PipelineSettings {
+    /// Whether the project at `path`, relative to the repository root, is ignored.
+    pub fn ignores(&self, path: &Path) -> bool {
+        matches_any(&self.ignore_projects, path)
+    }
+}
+
+impl TestSettings {
+    /// Whether the project at `path`, relative to the repository root, matches a test pattern.
+    pub fn matches(&self, path: &Path) -> bool {
+        matches_any(&self.patterns, path)
+    }
+}
+
+fn matches_any(patterns: &[String], path: &Path) -> bool {
+    patterns
+        .iter()
+        .any(|pattern| Glob::new(pattern).is_ok_and(|glob| glob.compile_matcher().is_match(path)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pipeline_settings() {
+        let config = Config::parse(
+            r#"
+[[pipeline]]
+name = "CustomerPortal"
+include = ["/CustomerPortal/*"]
+exclude = ["/CustomerPortal/apis/modules/*"]
+
+[[pipeline]]
+name = "*Portal"
+ignore_projects = ["**/Legacy/*.csproj"]
+
+[[pipeline]]
+path = "ci/build.yml"
+name = "Api"
+ignore_projects = ["**/Legacy/*.csproj"]
+"#,
+        )
+        .unwrap();
+
+        let settings = config.pipeline("CustomerPortal", Path::new("azure-pipelines.yml"));
+        assert_eq!(settings.include, vec!["/CustomerPortal/*"]);
+        assert_eq!(settings.exclude, vec!["/CustomerPortal/apis/modules/*"]);
+        assert!(settings.ignores(Path::new("CustomerPortal/Legacy/Legacy.csproj")));
+        assert!(!settings.ignores(Path::new("CustomerPortal/Api/Api.csproj")));
+
+        assert_eq!(
+            config.pipeline("RentalModule", Path::new("ci/rental.yml")),
+            PipelineSettings::default()
+        );
+        assert!(config
+            .tests
+            .matches(Path::new("Api.Tests/Api.Tests.csproj")));
+        assert!(config
+            .tests
+            .matches(Path::new("Api.UnitTests/Api.UnitTests.csproj")));
+        assert!(!config.tests.matches(Path::new("Contests/Contests.csproj")));
+        assert!(!config
+            .tests
+            .matches(Path::new("Foo.Testing.Helpers/Foo.Testing.Helpers.csproj")));
+        assert_eq!(
+            config
+                .pipeline("PartnerPortal", Path::new("ci/partner.yml"))
+                .include,
+            Vec::<String>::new()
+        );
+        assert_eq!(config.pipeline_name(Path::new("ci/build.yml")), Some("Api"));
+        assert_eq!(
+            config
+                .pipeline("Api", Path::new("ci/build.yml"))
+                .ignore_projects,
+            vec!["**/Legacy/*.csproj"]
+        );
+
+        let error = Config::parse("[[pipeline]]\nname = \"Portal\"\ninclde = []\n").unwrap_err();
+        assert!(error
+            .to_string()
+            .starts_with("failed to parse rami-deps.toml:3: unknown field `inclde`"));
+    }
+}
@@ -0,0 +1,468 @@
+//! A typed model of the parts of an Azure Pipelines YAML definition that matter for dependency
+//! tracking. Anything not modelled here is ignored while deserializing.
+
+use std::collections::BTreeMap;
+
+use serde::Deserialize;
+use serde_yaml::Value;
+
+use crate::solution::is_solution_file;
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Definition {
+    pub trigger: Option<Trigger>,
+    pub pr: Option<Trigger>,
+    pub parameters: Option<Parameters>,
+    pub variables: Option<Variables>,
+    #[serde(default)]
+    pub stages: Vec<Stage>,
+    #[serde(default)]
+    pub jobs: Vec<Job>,
+    #[serde(default)]
+    pub steps: Vec<Step>,
+    pub extends: Option<TemplateReference>,
+}
+
+/// A `template:` include together with the parameters passed to it.
+#[derive(Debug, Default, Deserialize)]
+pub struct TemplateReference {
+    pub template: String,
+    pub parameters: Option<Value>,
+}
+
+/// `trigger:` and `pr:` accept `none`, a plain branch list or the full form.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum Trigger {
+    None(String),
+    Branches(Vec<String>),
+    Full(TriggerDetails),
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TriggerDetails {
+    pub batch: Option<bool>,
+    pub branches: Option<IncludeExclude>,
+    pub paths: Option<IncludeExclude>,
+    pub tags: Option<IncludeExclude>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct IncludeExclude {
+    #[serde(default)]
+    pub include: Vec<String>,
+    #[serde(default)]
+    pub exclude: Vec<String>,
+}
+
+/// `parameters:` is a list of typed parameters, older definitions use a plain mapping of names to
+/// default values.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum Parameters {
+    List(Vec<Parameter>),
+    Map(BTreeMap<String, Value>),
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct Parameter {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub kind: Option<String>,
+    pub default: Option<Value>,
+}
+
+impl Parameters {
+    /// The default value of every parameter that has one.
+    pub fn defaults(&self) -> Vec<(String, Value)> {
+        match self {
+            Parameters::List(list) => list
+                .iter()
+                .filter_map(|parameter| Some((parameter.name.clone(), parameter.default.clone()?)))
+                .collect(),
+            Parameters::Map(map) => map
+                .iter()
+                .map(|(name, value)| (name.clone(), value.clone()))
+                .collect(),
+        }
+    }
+}
+
+/// `variables:` is either a plain mapping or a list of named variables, groups and templates.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum Variables {
+    Map(BTreeMap<String, Value>),
+    List(Vec<Variable>),
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct Variable {
+    pub name: Option<String>,
+    pub value: Option<Value>,
+    pub group: Option<String>,
+    pub template: Option<String>,
+    pub parameters: Option<Value>,
+}
+
+impl Variables {
+    /// The variables that have a literal value, groups and templates are skipped.
+    pub fn values(&self) -> Vec<(String, String)> {
+        match self {
+            Variables::Map(map) => map
+                .iter()
+                .filter_map(|(name, value)| Some((name.clone(), scalar(value)?)))
+                .collect(),
+            Variables::List(list) => list
+                .iter()
+                .filter_map(|variable| {
+                    Some((variable.name.clone()?, scalar(variable.value.as_ref()?)?))
+                })
+                .collect(),
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Stage {
+    pub stage: Option<String>,
+    pub display_name: Option<String>,
+    pub variables: Option<Variables>,
+    #[serde(default)]
+    pub jobs: Vec<Job>,
+    pub template: Option<String>,
+    pub parameters: Option<Value>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Job {
+    pub job: Option<String>,
+    pub deployment: Option<String>,
+    /// The environment a deployment job deploys to.
+    pub environment: Option<Environment>,
+    pub display_name: Option<String>,
+    pub variables: Option<Variables>,
+    #[serde(default)]
+    pub steps: Vec<Step>,
+    pub strategy: Option<Strategy>,
+    pub template: Option<String>,
+    pub parameters: Option<Value>,
+}
+
+impl Job {
+    /// All steps of the job, including the lifecycle hooks of deployment jobs.
+    pub fn all_steps(&self) -> Vec<&Step> {
+        let mut steps = self.steps.iter().collect::<Vec<_>>();
+        if let Some(strategy) = &self.strategy {
+            for lifecycle in [&strategy.run_once, &strategy.rolling, &strategy.canary]
+                .into_iter()
+                .flatten()
+            {
+                steps.extend(lifecycle.steps());
+            }
+        }
+
+        steps
+    }
+}
+
+/// `environment:` is either `name` (or `name.resource`) or a mapping with the name.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum Environment {
+    Name(String),
+    Details {
+        name: String,
+        #[serde(rename = "resourceName")]
+        resource_name: Option<String>,
+    },
+}
+
+impl Environment {
+    /// The environment and resource as Azure DevOps shows them, e.g. `Production.web-01`.
+    pub fn target(&self) -> String {
+        match self {
+            Environment::Name(name) => name.clone(),
+            Environment::Details {
+                name,
+                resource_name: Some(resource),
+            } => format!("{}.{}", name, resource),
+            Environment::Details { name, .. } => name.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Strategy {
+    pub run_once: Option<Lifecycle>,
+    pub rolling: Option<Lifecycle>,
+    pub canary: Option<Lifecycle>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Lifecycle {
+    pub pre_deploy: Option<Hook>,
+    pub deploy: Option<Hook>,
+    pub route_traffic: Option<Hook>,
+    pub post_route_traffic: Option<Hook>,
+    pub on: Option<OnHooks>,
+}
+
+impl Lifecycle {
+    fn steps(&self) -> impl Iterator<Item = &Step> {
+        let on = self.on.as_ref();
+        [
+            self.pre_deploy.as_ref(),
+            self.deploy.as_ref(),
+            self.route_traffic.as_ref(),
+            self.post_route_traffic.as_ref(),
+            on.and_then(|on| on.failure.as_ref()),
+            on.and_then(|on| on.success.as_ref()),
+        ]
+        .into_iter()
+        .flatten()
+        .flat_map(|hook| hook.steps.iter())
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct OnHooks {
+    pub failure: Option<Hook>,
+    pub success: Option<Hook>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct Hook {
+    #[serde(default)]
+    pub steps: Vec<Step>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Step {
+    pub task: Option<String>,
+    pub script: Option<String>,
+    pub bash: Option<String>,
+    pub pwsh: Option<String>,
+    pub powershell: Option<String>,
+    pub display_name: Option<String>,
+    pub working_directory: Option<String>,
+    #[serde(default)]
+    pub inputs: BTreeMap<String, Value>,
+    pub template: Option<String>,
+    pub parameters: Option<Value>,
+}
+
+impl Step {
+    fn input(&self, name: &str) -> Option<String> {
+        self.inputs
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .and_then(|(_, value)| scalar(value))
+    }
+
+    /// Path patterns of the projects this step builds, relative to the repository root. The
+    /// patterns may still contain variables and globs.
+    pub fn project_patterns(&self) -> Vec<String> {
+        let working_directory = self
+            .working_directory
+            .clone()
+            .or_else(|| self.input("workingDirectory"));
+
+        let mut patterns = Vec::new();
+        // `solution` is what the VSBuild and MSBuild tasks call it.
+        for projects in ["projects", "solution"]
+            .map(|input| self.input(input))
+            .into_iter()
+            .flatten()
+        {
+            patterns.extend(
+                projects
+                    .lines()
+                    .map(str::trim)
+                    .filter(|line| !line.is_empty())
+                    .map(str::to_string),
+            );
+        }
+
+        let scripts = [&self.script, &self.bash, &self.pwsh, &self.powershell]
+            .into_iter()
+            .flatten()
+            .cloned()
+            .chain(
+                ["script", "inlineScript", "arguments"]
+                    .map(|input| self.input(input))
+                    .into_iter()
+                    .flatten(),
+            );
+        let mut runs_dotnet = self
+            .task
+            .as_deref()
+            .is_some_and(|task| task.starts_with("DotNetCoreCLI"));
+        for script in scripts {
+            for token in script.split_whitespace() {
+                let token = token.trim_matches(|c| c == '"' || c == '\'');
+                if token == "dotnet" {
+                    runs_dotnet = true;
+                } else if is_project_file(token) || is_solution_file(token) {
+                    patterns.push(join(working_directory.as_deref(), token));
+                }
+            }
+        }
+
+        // `dotnet build` without a project builds the project or solution in the working
+        // directory.
+        if patterns.is_empty() && runs_dotnet {
+            for pattern in ["*.csproj", "*.sln", "*.slnx"] {
+                patterns.push(join(working_directory.as_deref(), pattern));
+            }
+        }
+
+        patterns
+    }
+}
+
+impl Definition {
+    /// Every step in the definition, regardless of whether it is declared at the top level, in a
+    /// job or in a stage.
+    pub fn all_steps(&self) -> Vec<&Step> {
+        let mut steps = self.steps.iter().collect::<Vec<_>>();
+        for job in self
+            .stages
+            .iter()
+            .flat_map(|stage| &stage.jobs)
+            .chain(&self.jobs)
+        {
+            steps.extend(job.all_steps());
+        }
+
+        steps
+    }
+}
+
+pub(crate) fn is_project_file(path: &str) -> bool {
+    path.to_ascii_lowercase().ends_with(".csproj")
+}
+
+fn join(directory: Option<&str>, path: &str) -> String {
+    match directory {
+        Some(directory) if !directory.is_empty() => {
+            format!("{}/{}", directory.trim_end_matches(['/', '\\']), path)
+        }
+        _ => path.to_string(),
+    }
+}
+
+/// Converts a YAML scalar to the string Azure Pipelines would see.
+pub(crate) fn scalar(value: &Value) -> Option<String> {
+    match value {
+        Value::String(value) => Some(value.clone()),
+        Value::Bool(value) => Some(value.to_string()),
+        Value::Number(value) => Some(value.to_string()),
+        _ => None,
+    }
+}
+
+/// Converts a parameter value to a string. Lists of scalars become one item per line, which is
+/// how multi-line task inputs such as `projects:` expect them.
+pub(crate) fn parameter_value(value: &Value) -> Option<String> {
+    match value {
+        Value::Sequence(items) => Some(
+            items
+                .iter()
+                .filter_map(scalar)
+                .collect::<Vec<_>>()
+                .join("\n"),
+        ),
+        value => scalar(value),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_project_patterns() {
+        let input = r#"
+trigger:
+  branches:
+    include:
+      - main
+  paths:
+    include:
+      - CustomerPortal/apis/modules/RentalModule
+
+variables:
+  projectPath: "CustomerPortal/apis/modules/RentalModule/RentalModule.Api/RentalModule.Api.csproj"
+
+stages:
+  - stage: Build
+    jobs:
+      - job: RentalModuleApi
+        steps:
+          # - task: DotNetCoreCLI@2
+          #   inputs:
+          #     projects: "CustomerPortal/apis/modules/Old/Old.csproj"
+          - task: DotNetCoreCLI@2
+            inputs:
+              command: build
+              projects: |
+                $(projectPath)
+                CustomerPortal/apis/**/*.csproj
+          - script: dotnet publish src/Foo/Foo.csproj --configuration Release
+          - script: dotnet build Backend/Backend.sln
+          - script: dotnet test
+            workingDirectory: CustomerPortal/apis/modules/RentalModule/RentalModule.Tests
+  - stage: Deploy
+    jobs:
+      - deployment: DeployToDev
+        environment:
+          name: Development
+          resourceName: web-01
+        strategy:
+          runOnce:
+            deploy:
+              steps:
+                - bash: dotnet run --project 'tools/Migrator/Migrator.csproj'
+"#;
+
+        let definition: Definition = serde_yaml::from_str(input).unwrap();
+        let patterns = definition
+            .all_steps()
+            .into_iter()
+            .flat_map(Step::project_patterns)
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            patterns,
+            vec![
+                "$(projectPath)",
+                "CustomerPortal/apis/**/*.csproj",
+                "src/Foo/Foo.csproj",
+                "Backend/Backend.sln",
+                "CustomerPortal/apis/modules/RentalModule/RentalModule.Tests/*.csproj",
+                "CustomerPortal/apis/modules/RentalModule/RentalModule.Tests/*.sln",
+                "CustomerPortal/apis/modules/RentalModule/RentalModule.Tests/*.slnx",
+                "tools/Migrator/Migrator.csproj",
+            ]
+        );
+        assert_eq!(
+            definition.stages[1].jobs[0]
+                .environment
+                .as_ref()
+                .map(Environment::target),
+            Some("Development.web-01".to_string())
+        );
+        assert!(matches!(
+            definition.trigger,
+            Some(Trigger::Full(TriggerDetails { paths: Some(_), .. }))
+        ));
+    }
+}
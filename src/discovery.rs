@@ -0,0 +1,172 @@
+//! Discovery of the pipeline files in a directory tree.
+
+use std::{
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use ignore::{WalkBuilder, WalkState};
+
+use crate::{config::DiscoverySettings, definition::is_project_file, repo};
+
+/// Directories that never contain pipelines or projects of their own, but build output,
+/// restored packages and the like, which can be large.
+const SKIPPED_DIRS: &[&str] = &[
+    ".git",
+    ".vs",
+    ".idea",
+    ".rami-api-deps",
+    "node_modules",
+    "bower_components",
+    "bin",
+    "obj",
+    "TestResults",
+    "dist",
+];
+
+/// Finds every pipeline file below `root_dir` matching the discovery patterns relative to
+/// `repo_root`, walking the tree on `threads` threads (0 picks a number based on the available
+/// cores). The result is sorted, so it does not depend on the order the threads happen to
+/// finish in.
+pub fn find_pipelines(
+    repo_root: &Path,
+    root_dir: &Path,
+    settings: &DiscoverySettings,
+    threads: usize,
+) -> Vec<PathBuf> {
+    let patterns = glob_set(&settings.pipelines);
+    find_files(repo_root, root_dir, settings, threads, |path| {
+        patterns.is_match(repo::relative_to(repo_root, path))
+    })
+}
+
+/// Finds every project file below `root_dir`, like [`find_pipelines`].
+pub fn find_projects(
+    repo_root: &Path,
+    root_dir: &Path,
+    settings: &DiscoverySettings,
+    threads: usize,
+) -> Vec<PathBuf> {
+    find_files(repo_root, root_dir, settings, threads, |path| {
+        path.file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(is_project_file)
+    })
+}
+
+/// Walks `root_dir` for files that `matches`, honouring `.gitignore` and `.ignore` files and
+/// skipping [`SKIPPED_DIRS`] and the excluded paths.
+fn find_files(
+    repo_root: &Path,
+    root_dir: &Path,
+    settings: &DiscoverySettings,
+    threads: usize,
+    matches: impl Fn(&Path) -> bool + Sync,
+) -> Vec<PathBuf> {
+    let exclude = glob_set(&settings.exclude);
+    let repo_root = repo_root.to_path_buf();
+
+    let found = Mutex::new(Vec::new());
+    WalkBuilder::new(root_dir)
+        .standard_filters(false)
+        .git_ignore(true)
+        .git_exclude(true)
+        .ignore(true)
+        .parents(true)
+        // Also honour `.gitignore` files in a copy of the repository without `.git`.
+        .require_git(false)
+        .filter_entry(move |entry| {
+            let skipped = entry.depth() > 0
+                && entry.file_type().is_some_and(|t| t.is_dir())
+                && entry
+                    .file_name()
+                    .to_str()
+                    .is_some_and(|name| SKIPPED_DIRS.contains(&name));
+            !skipped && !exclude.is_match(repo::relative_to(&repo_root, entry.path()))
+        })
+        .threads(threads)
+        .build_parallel()
+        .run(|| {
+            Box::new(|entry| {
+                // Skip entries that cannot be accessed
+                if let Ok(entry) = entry {
+                    if entry
+                        .file_type()
+                        .is_some_and(|file_type| file_type.is_file())
+                        && matches(entry.path())
+                    {
+                        found.lock().unwrap().push(entry.into_path());
+                    }
+                }
+                WalkState::Continue
+            })
+        });
+
+    let mut found = found.into_inner().unwrap();
+    found.sort();
+    found
+}
+
+/// Invalid patterns are left out, they are reported when the config is loaded.
+fn glob_set(patterns: &[String]) -> GlobSet {
+    let mut builder = GlobSetBuilder::new();
+    for glob in patterns
+        .iter()
+        .filter_map(|pattern| Glob::new(pattern).ok())
+    {
+        builder.add(glob);
+    }
+    builder.build().unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::write_tree;
+
+    #[test]
+    fn test_find_pipelines() {
+        let dir = tempfile::tempdir().unwrap();
+        write_tree(
+            dir.path(),
+            &[
+                ("b/azure-pipelines.yml", ""),
+                ("a/nested/azure-pipelines.yml", ""),
+                ("a/azure-pipelines.yaml", ""),
+                ("a/azure-pipelines.yml/not-a-file.txt", ""),
+                ("a/release.azure-pipelines.yml", ""),
+                ("a/pipelines.yml", ""),
+                ("ci/build.yml", ""),
+                ("web/node_modules/tool/azure-pipelines.yml", ""),
+                ("Api/bin/Release/azure-pipelines.yml", ""),
+                ("generated/azure-pipelines.yml", ""),
+                ("legacy/azure-pipelines.yml", ""),
+                (".gitignore", "generated/\n"),
+            ],
+        );
+
+        let settings = DiscoverySettings {
+            exclude: vec!["legacy".to_string()],
+            ..DiscoverySettings::default()
+        };
+        assert_eq!(
+            find_pipelines(dir.path(), dir.path(), &settings, 0),
+            vec![
+                dir.path().join("a/azure-pipelines.yaml"),
+                dir.path().join("a/nested/azure-pipelines.yml"),
+                dir.path().join("a/release.azure-pipelines.yml"),
+                dir.path().join("b/azure-pipelines.yml"),
+            ]
+        );
+
+        let settings = DiscoverySettings {
+            pipelines: vec!["ci/*.yml".to_string()],
+            ..DiscoverySettings::default()
+        };
+        assert_eq!(
+            find_pipelines(dir.path(), &dir.path().join("ci"), &settings, 0),
+            vec![dir.path().join("ci/build.yml")]
+        );
+    }
+}
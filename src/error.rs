@@ -0,0 +1,86 @@
+//! The errors that can occur while reading pipelines and projects.
+
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use thiserror::Error;
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// Errors are cloneable so that a problem with a project shared by several pipelines can be
+/// reported for each of them.
+#[derive(Debug, Clone, Error)]
+pub enum Error {
+    #[error("failed to read {}: {source}", path.display())]
+    IoError {
+        path: PathBuf,
+        #[source]
+        source: Arc<std::io::Error>,
+    },
+
+    /// `line` is 1-based, or 0 when the parser does not know where the problem is.
+    #[error("failed to parse {}:{line}: {message}", file.display())]
+    ParseError {
+        file: PathBuf,
+        line: usize,
+        message: String,
+    },
+
+    #[error("{} references {}, which does not exist", project.display(), reference.display())]
+    MissingReference {
+        project: PathBuf,
+        reference: PathBuf,
+    },
+
+    #[error("could not resolve `{expression}` in {}", file.display())]
+    UnresolvedVariable { file: PathBuf, expression: String },
+
+    #[error("reference cycle: {}", display_cycle(cycle))]
+    ReferenceCycle { cycle: Vec<PathBuf> },
+
+    #[error("git failed: {message}")]
+    GitError { message: String },
+
+    #[error("path is not valid UTF-8: {}", path.display())]
+    InvalidPath { path: PathBuf },
+}
+
+impl Error {
+    pub fn io(path: &Path, source: std::io::Error) -> Self {
+        Self::IoError {
+            path: path.to_path_buf(),
+            source: Arc::new(source),
+        }
+    }
+
+    pub(crate) fn yaml(file: &Path, error: serde_yaml::Error) -> Self {
+        Self::ParseError {
+            file: file.to_path_buf(),
+            line: error.location().map_or(0, |location| location.line()),
+            message: error.to_string(),
+        }
+    }
+
+    pub(crate) fn xml(file: &Path, error: roxmltree::Error) -> Self {
+        Self::ParseError {
+            file: file.to_path_buf(),
+            line: error.pos().row as usize,
+            message: error.to_string(),
+        }
+    }
+}
+
+fn display_cycle(cycle: &[PathBuf]) -> String {
+    cycle
+        .iter()
+        .map(|path| path.display().to_string())
+        .collect::<Vec<_>>()
+        .join(" -> ")
+}
+
+/// Reads a file relative to `repo_root`, reporting errors with the relative `path`.
+pub(crate) fn read_to_string(repo_root: &Path, path: &Path) -> Result<String> {
+    std::fs::read_to_string(repo_root.join(path)).map_err(|e| Error::io(path, e))
+}
@@ -0,0 +1,370 @@
+//! Evaluation of a project together with the files it imports, following MSBuild's passes:
+//! first properties and imports in document order, then items with the final properties.
+//!
+//! Besides explicit `<Import>`s, the nearest `Directory.Build.props` and
+//! `Directory.Packages.props` are imported before the project and the nearest
+//! `Directory.Build.targets` after it, like the .NET SDK does.
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    condition, error,
+    msbuild::{find_metadata, Element, Item, ItemGroup, ProjectFile},
+    properties::{BuildContext, Properties},
+    repo, Error, Result,
+};
+
+/// The file holding the package versions for Central Package Management.
+pub const DIRECTORY_PACKAGES_PROPS: &str = "Directory.Packages.props";
+
+/// A project after evaluation.
+#[derive(Debug, Default)]
+pub struct EvaluatedProject {
+    pub properties: Properties,
+    /// Files imported by the project, relative to the repository root, in evaluation order.
+    pub imports: Vec<PathBuf>,
+    /// Explicitly imported files that do not exist, relative to the repository root. Creating
+    /// one of them changes the evaluation.
+    pub missing_imports: Vec<PathBuf>,
+    /// The items whose conditions are met, one per entry of a `;` separated `Include`.
+    pub items: Vec<EvaluatedItem>,
+}
+
+#[derive(Debug, Clone)]
+pub struct EvaluatedItem {
+    pub kind: String,
+    /// The include with properties expanded. Relative paths are relative to the project.
+    pub include: String,
+    pub condition: Option<String>,
+    pub metadata: BTreeMap<String, String>,
+}
+
+impl EvaluatedItem {
+    /// Like [`Item::metadata`].
+    pub fn metadata(&self, name: &str) -> Option<&str> {
+        find_metadata(&self.metadata, name)
+    }
+}
+
+impl EvaluatedProject {
+    /// The included paths of every item of the given type.
+    pub fn items<'a>(&'a self, kind: &'a str) -> impl Iterator<Item = &'a EvaluatedItem> {
+        self.items.iter().filter(move |item| item.kind == kind)
+    }
+}
+
+/// Evaluates the project at `project_path`, relative to `repo_root`. Imports that do not exist
+/// are skipped, imports that cannot be read or parsed are errors.
+pub fn evaluate(
+    repo_root: &Path,
+    project_path: &Path,
+    context: &BuildContext,
+) -> Result<EvaluatedProject> {
+    let mut evaluator = Evaluator {
+        repo_root,
+        properties: context.project_properties(repo_root, project_path),
+        imports: Vec::new(),
+        missing_imports: Vec::new(),
+        item_groups: Vec::new(),
+    };
+
+    let project_dir = project_path.parent().unwrap_or(Path::new(""));
+    if let Some(props) = find_above(repo_root, project_dir, "Directory.Build.props") {
+        evaluator.import(&props)?;
+    }
+    let import_packages_props = evaluator
+        .properties
+        .get("ImportDirectoryPackagesProps")
+        .is_none_or(|value| !value.trim().eq_ignore_ascii_case("false"));
+    if let Some(props) = find_above(repo_root, project_dir, DIRECTORY_PACKAGES_PROPS)
+        .filter(|_| import_packages_props)
+    {
+        evaluator.import(&props)?;
+    }
+    evaluator.evaluate_file(project_path, read_project_file(repo_root, project_path)?)?;
+    if let Some(targets) = find_above(repo_root, project_dir, "Directory.Build.targets") {
+        evaluator.import(&targets)?;
+    }
+
+    Ok(evaluator.evaluate_items(project_path))
+}
+
+struct Evaluator<'a> {
+    repo_root: &'a Path,
+    properties: Properties,
+    imports: Vec<PathBuf>,
+    missing_imports: Vec<PathBuf>,
+    /// Item groups of every evaluated file, with the file they are declared in.
+    item_groups: Vec<(PathBuf, ItemGroup)>,
+}
+
+impl Evaluator<'_> {
+    fn is_met(&self, condition: &Option<String>, file: &Path) -> bool {
+        is_met(self.repo_root, &self.properties, condition, file)
+    }
+
+    fn evaluate_file(&mut self, file: &Path, project_file: ProjectFile) -> Result<()> {
+        self.properties.set_this_file(&self.repo_root.join(file));
+
+        for element in project_file.elements {
+            match element {
+                Element::PropertyGroup(property_group) => {
+                    if !self.is_met(&property_group.condition, file) {
+                        continue;
+                    }
+                    for property in property_group.properties {
+                        if self.is_met(&property.condition, file) {
+                            let value = self.properties.expand(&property.value);
+                            self.properties.set(&property.name, value);
+                        }
+                    }
+                }
+                Element::Import(import) => {
+                    let directory = file.parent().unwrap_or(Path::new(""));
+                    let project = self.properties.expand(&import.project).replace('\\', "/");
+                    if !self.is_met(&import.condition, file) {
+                        // Usually an `Exists()` guard, which holds once the file is created.
+                        if !project.contains(['*', '?']) {
+                            for path in resolve_import(self.repo_root, directory, &project) {
+                                self.note_missing(&path);
+                            }
+                        }
+                        continue;
+                    }
+                    for path in resolve_import(self.repo_root, directory, &project) {
+                        self.import(&path)?;
+                    }
+                    // Importing changes the current file, so point it back at this one.
+                    self.properties.set_this_file(&self.repo_root.join(file));
+                }
+            }
+        }
+
+        self.item_groups.extend(
+            project_file
+                .item_groups
+                .into_iter()
+                .map(|item_group| (file.to_path_buf(), item_group)),
+        );
+
+        Ok(())
+    }
+
+    /// Imports `path`, relative to the repository root. Files outside the repository, missing
+    /// files and files that were already imported are skipped, the latter like MSBuild does.
+    fn import(&mut self, path: &Path) -> Result<()> {
+        if path.is_absolute() || path.starts_with("..") || self.imports.iter().any(|i| i == path) {
+            return Ok(());
+        }
+        if !self.repo_root.join(path).is_file() {
+            self.note_missing(path);
+            return Ok(());
+        }
+
+        self.imports.push(path.to_path_buf());
+        let project_file = read_project_file(self.repo_root, path)?;
+        self.evaluate_file(path, project_file)
+    }
+
+    /// Records `path`, relative to the repository root, if it is a missing file in the
+    /// repository.
+    fn note_missing(&mut self, path: &Path) {
+        let outside = path.is_absolute() || path.starts_with("..");
+        if !outside
+            && !self.repo_root.join(path).is_file()
+            && !self.missing_imports.iter().any(|p| p == path)
+        {
+            self.missing_imports.push(path.to_path_buf());
+        }
+    }
+
+    fn evaluate_items(self, project_path: &Path) -> EvaluatedProject {
+        let mut items = Vec::new();
+        for (file, item_group) in &self.item_groups {
+            // `MSBuildThisFile*` refers to the file the item is declared in.
+            let mut properties = self.properties.clone();
+            properties.set_this_file(&self.repo_root.join(file));
+
+            // Relative paths in item conditions and includes are relative to the project.
+            if !is_met(
+                self.repo_root,
+                &properties,
+                &item_group.condition,
+                project_path,
+            ) {
+                continue;
+            }
+            for item in &item_group.items {
+                if is_met(self.repo_root, &properties, &item.condition, project_path) {
+                    items.extend(evaluate_item(item, &properties));
+                }
+            }
+        }
+
+        let mut properties = self.properties;
+        properties.set_this_file(&self.repo_root.join(project_path));
+
+        EvaluatedProject {
+            properties,
+            imports: self.imports,
+            missing_imports: self.missing_imports,
+            items,
+        }
+    }
+}
+
+/// Evaluates `condition`, with relative paths in `Exists()` resolved from the directory of
+/// `file`.
+fn is_met(
+    repo_root: &Path,
+    properties: &Properties,
+    condition: &Option<String>,
+    file: &Path,
+) -> bool {
+    let directory = repo_root.join(file.parent().unwrap_or(Path::new("")));
+    let exists = |path: &str| directory.join(path.replace('\\', "/")).exists();
+    condition
+        .as_deref()
+        .is_none_or(|condition| condition::evaluate(condition, properties, &exists))
+}
+
+fn evaluate_item(item: &Item, properties: &Properties) -> Vec<EvaluatedItem> {
+    item.includes(properties)
+        .into_iter()
+        .map(|include| EvaluatedItem {
+            kind: item.kind.clone(),
+            include,
+            condition: item.condition.clone(),
+            metadata: item.metadata.clone(),
+        })
+        .collect()
+}
+
+fn read_project_file(repo_root: &Path, path: &Path) -> Result<ProjectFile> {
+    let input = error::read_to_string(repo_root, path)?;
+    ProjectFile::parse(&input).map_err(|e| Error::xml(path, e))
+}
+
+/// Resolves the `Project` of an import, which may be relative to the importing file, absolute or
+/// contain wildcards. Returned paths are relative to the repository root.
+fn resolve_import(repo_root: &Path, directory: &Path, project: &str) -> Vec<PathBuf> {
+    let path = repo_root.join(directory).join(project);
+    if !project.contains(['*', '?']) {
+        return vec![repo::relative_to(repo_root, &path)];
+    }
+
+    let Ok(paths) = glob::glob(path.to_str().unwrap_or_default()) else {
+        return Vec::new();
+    };
+    let mut paths = paths
+        .filter_map(Result::ok)
+        .map(|path| repo::relative_to(repo_root, &path))
+        .collect::<Vec<_>>();
+    paths.sort();
+
+    paths
+}
+
+/// Finds the nearest `file_name` in `directory` or above it, without leaving the repository.
+pub(crate) fn find_above(repo_root: &Path, directory: &Path, file_name: &str) -> Option<PathBuf> {
+    directory
+        .ancestors()
+        .map(|directory| directory.join(file_name))
+        .find(|path| repo_root.join(path).is_file())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::write_tree;
+
+    #[test]
+    fn test_evaluate_imports() {
+        let dir = tempfile::tempdir().unwrap();
+        write_tree(
+            dir.path(),
+            &[
+                (
+                    "Directory.Build.props",
+                    r#"<Project>
+  <PropertyGroup>
+    <SharedDir>$(MSBuildThisFileDirectory)SharedLibraries\</SharedDir>
+  </PropertyGroup>
+  <ItemGroup>
+    <ProjectReference Include="$(SharedDir)Analyzers\Analyzers.csproj" />
+  </ItemGroup>
+</Project>"#,
+                ),
+                (
+                    "CustomerPortal/Directory.Build.props",
+                    r#"<Project>
+  <Import Project="$([MSBuild]::GetPathOfFileAbove('Directory.Build.props', '$(MSBuildThisFileDirectory)../'))" />
+  <Import Project="build\common.props" Condition="'$(Configuration)' == 'Release'" />
+</Project>"#,
+                ),
+                (
+                    "CustomerPortal/build/common.props",
+                    r#"<Project>
+  <PropertyGroup>
+    <CommonName>Common</CommonName>
+  </PropertyGroup>
+</Project>"#,
+                ),
+                (
+                    "CustomerPortal/Directory.Build.targets",
+                    r#"<Project>
+  <ItemGroup>
+    <ProjectReference Include="$(SharedDir)$(CommonName)\$(CommonName).csproj" />
+  </ItemGroup>
+</Project>"#,
+                ),
+                (
+                    "CustomerPortal/apis/Api/Api.csproj",
+                    r#"<Project Sdk="Microsoft.NET.Sdk">
+  <Import Project="Sdk.props" Sdk="Microsoft.NET.Sdk" />
+  <ItemGroup>
+    <ProjectReference Include="..\Domain\Domain.csproj" />
+  </ItemGroup>
+</Project>"#,
+                ),
+            ],
+        );
+
+        let evaluated = evaluate(
+            dir.path(),
+            Path::new("CustomerPortal/apis/Api/Api.csproj"),
+            &BuildContext::default(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            evaluated.imports,
+            vec![
+                PathBuf::from("CustomerPortal/Directory.Build.props"),
+                PathBuf::from("Directory.Build.props"),
+                PathBuf::from("CustomerPortal/build/common.props"),
+                PathBuf::from("CustomerPortal/Directory.Build.targets"),
+            ]
+        );
+        assert_eq!(
+            evaluated
+                .items("ProjectReference")
+                .map(|item| {
+                    let include = item.include.replace('\\', "/");
+                    repo::relative_to(
+                        dir.path(),
+                        &dir.path().join("CustomerPortal/apis/Api").join(include),
+                    )
+                })
+                .collect::<Vec<_>>(),
+            vec![
+                PathBuf::from("SharedLibraries/Analyzers/Analyzers.csproj"),
+                PathBuf::from("CustomerPortal/apis/Domain/Domain.csproj"),
+                PathBuf::from("SharedLibraries/Common/Common.csproj"),
+            ]
+        );
+    }
+}
@@ -0,0 +1,211 @@
+//! Export of the project reference graph as Graphviz DOT or Mermaid, to see why a pipeline
+//! depends on what it depends on.
+
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    path::{Path, PathBuf},
+};
+
+use crate::{graph::DependencyGraph, pipeline::Pipeline, project::Project};
+
+/// The pipelines and projects to draw. Pipelines point at the projects they build, projects at
+/// the projects they reference.
+pub struct Export<'a> {
+    pipelines: Vec<&'a Pipeline>,
+    projects: BTreeMap<&'a Path, &'a Project>,
+    graph: &'a DependencyGraph,
+}
+
+impl<'a> Export<'a> {
+    /// Every project in `graph` and every pipeline in `pipelines`.
+    pub fn new(graph: &'a DependencyGraph, pipelines: &'a [Pipeline]) -> Self {
+        Self {
+            pipelines: pipelines.iter().collect(),
+            projects: graph
+                .projects()
+                .map(|project| (project.path.as_path(), project))
+                .collect(),
+            graph,
+        }
+    }
+
+    /// Only `pipeline` and the projects it builds or references.
+    pub fn pipeline(graph: &'a DependencyGraph, pipeline: &'a Pipeline) -> Self {
+        Self {
+            pipelines: vec![pipeline],
+            projects: pipeline
+                .projects
+                .iter()
+                .chain(&pipeline.dependencies)
+                .map(|project| (project.path.as_path(), project))
+                .collect(),
+            graph,
+        }
+    }
+
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph dependencies {\n    rankdir=LR;\n");
+        dot.push_str("    node [shape=box, fontname=\"Helvetica\"];\n\n");
+        for (i, pipeline) in self.pipelines.iter().enumerate() {
+            let _ = writeln!(
+                dot,
+                "    pipeline{} [label=\"{}\", tooltip=\"{}\", shape=hexagon, style=filled, fillcolor=\"#cfe2ff\"];",
+                i,
+                escape_dot(&pipeline.name),
+                escape_dot(&pipeline.path.to_string_lossy())
+            );
+        }
+        for (i, project) in self.projects.values().enumerate() {
+            let style = if project.is_test {
+                ", style=\"dashed,filled\", fillcolor=\"#eeeeee\""
+            } else {
+                ""
+            };
+            let _ = writeln!(
+                dot,
+                "    project{} [label=\"{}\", tooltip=\"{}\"{}];",
+                i,
+                escape_dot(&project.name()),
+                escape_dot(&project.path.to_string_lossy()),
+                style
+            );
+        }
+        dot.push('\n');
+        for (from, to) in self.edges() {
+            let _ = writeln!(dot, "    {} -> {};", from, to);
+        }
+        dot.push_str("}\n");
+
+        dot
+    }
+
+    pub fn to_mermaid(&self) -> String {
+        let mut mermaid = String::from("flowchart LR\n");
+        for (i, pipeline) in self.pipelines.iter().enumerate() {
+            let _ = writeln!(
+                mermaid,
+                "    pipeline{}{{{{\"{}\"}}}}",
+                i,
+                escape_mermaid(&pipeline.name)
+            );
+        }
+        for (i, project) in self.projects.values().enumerate() {
+            let _ = writeln!(
+                mermaid,
+                "    project{}[\"{}\"]",
+                i,
+                escape_mermaid(&project.name())
+            );
+        }
+        for (from, to) in self.edges() {
+            let _ = writeln!(mermaid, "    {} --> {}", from, to);
+        }
+
+        mermaid.push_str("    classDef pipeline fill:#cfe2ff,stroke:#084298\n");
+        mermaid.push_str("    classDef test fill:#eeeeee,stroke-dasharray:5 5\n");
+        for i in 0..self.pipelines.len() {
+            let _ = writeln!(mermaid, "    class pipeline{} pipeline", i);
+        }
+        for (i, project) in self.projects.values().enumerate() {
+            if project.is_test {
+                let _ = writeln!(mermaid, "    class project{} test", i);
+            }
+        }
+
+        mermaid
+    }
+
+    /// The edges between node ids, pipelines to their projects first.
+    fn edges(&self) -> Vec<(String, String)> {
+        let ids = self
+            .projects
+            .keys()
+            .enumerate()
+            .map(|(i, path)| (*path, format!("project{}", i)))
+            .collect::<BTreeMap<_, _>>();
+        let edge = |from: String, to: &PathBuf| ids.get(to.as_path()).map(|to| (from, to.clone()));
+
+        let mut edges = Vec::new();
+        for (i, pipeline) in self.pipelines.iter().enumerate() {
+            edges.extend(
+                pipeline
+                    .projects
+                    .iter()
+                    .filter_map(|project| edge(format!("pipeline{}", i), &project.path)),
+            );
+        }
+        for (path, id) in &ids {
+            edges.extend(
+                self.graph
+                    .references(path)
+                    .iter()
+                    .filter_map(|reference| edge(id.clone(), reference)),
+            );
+        }
+
+        edges
+    }
+}
+
+fn escape_dot(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+fn escape_mermaid(text: &str) -> String {
+    text.replace('"', "#quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{config::Config, properties::BuildContext, testing::write_tree};
+
+    #[test]
+    fn test_export() {
+        let dir = tempfile::tempdir().unwrap();
+        write_tree(
+            dir.path(),
+            &[
+                (
+                    "Api/Api.csproj",
+                    r#"<Project><ItemGroup><ProjectReference Include="..\Core\Core.csproj" /></ItemGroup></Project>"#,
+                ),
+                ("Core/Core.csproj", "<Project />"),
+                ("Other/Other.csproj", "<Project />"),
+                (
+                    "Rental/azure-pipelines.yml",
+                    "steps:\n  - task: DotNetCoreCLI@2\n    inputs:\n      projects: Api/Api.csproj\n",
+                ),
+            ],
+        );
+
+        let mut graph = DependencyGraph::new(dir.path(), &BuildContext::default());
+        graph.add(Path::new("Other/Other.csproj"));
+        let pipeline = Pipeline::new(
+            &mut graph,
+            PathBuf::from("Rental/azure-pipelines.yml"),
+            &Config::default(),
+        )
+        .unwrap();
+
+        let export = Export::pipeline(&graph, &pipeline);
+        assert_eq!(
+            export.to_mermaid(),
+            r#"flowchart LR
+    pipeline0{{"Rental"}}
+    project0["Api"]
+    project1["Core"]
+    pipeline0 --> project0
+    project0 --> project1
+    classDef pipeline fill:#cfe2ff,stroke:#084298
+    classDef test fill:#eeeeee,stroke-dasharray:5 5
+    class pipeline0 pipeline
+"#
+        );
+
+        let dot = Export::new(&graph, std::slice::from_ref(&pipeline)).to_dot();
+        assert!(dot.contains("project2 [label=\"Other\", tooltip=\"Other/Other.csproj\"];"));
+        assert!(dot.contains("    pipeline0 -> project0;\n    project0 -> project1;\n}"));
+    }
+}
@@ -0,0 +1,89 @@
+//! Azure DevOps path filters, as written to `.azure-pathfilter` files.
+
+use std::fmt::{Display, Formatter};
+
+use serde::{Deserialize, Serialize};
+
+/// Include and exclude paths. Written as `;` terminated entries separated by spaces, with
+/// excludes prefixed by `!`, e.g. `!/apis/modules/*; /apis/Shared/*; /build.yml;`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct PathFilter {
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+}
+
+impl PathFilter {
+    pub fn parse(input: &str) -> Self {
+        let mut filter = PathFilter::default();
+        for entry in input.split(';').map(str::trim).filter(|e| !e.is_empty()) {
+            match entry.strip_prefix('!') {
+                Some(exclude) => filter.exclude.push(exclude.trim().to_string()),
+                None => filter.include.push(entry.to_string()),
+            }
+        }
+
+        filter
+    }
+
+    /// Whether a change to `path` triggers the pipeline: it matches an include and no exclude,
+    /// like in Azure DevOps. `path` is relative to the repository root, with or without the
+    /// leading `/`.
+    pub fn matches(&self, path: &str) -> bool {
+        let path = format!("/{}", path.replace('\\', "/").trim_start_matches('/'));
+        self.include.iter().any(|pattern| matches(pattern, &path))
+            && !self.exclude.iter().any(|pattern| matches(pattern, &path))
+    }
+}
+
+impl Display for PathFilter {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let entries = self
+            .exclude
+            .iter()
+            .map(|exclude| format!("!{};", exclude))
+            .chain(self.include.iter().map(|include| format!("{};", include)))
+            .collect::<Vec<_>>();
+
+        write!(f, "{}", entries.join(" "))
+    }
+}
+
+/// Patterns without wildcards match the path itself and everything below it, `*` and `?` match
+/// any characters including `/`.
+fn matches(pattern: &str, path: &str) -> bool {
+    if !pattern.contains(['*', '?']) {
+        let pattern = pattern.trim_end_matches('/');
+        return path == pattern
+            || path
+                .strip_prefix(pattern)
+                .is_some_and(|rest| rest.starts_with('/'));
+    }
+
+    glob::Pattern::new(
+        &glob::Pattern::escape(pattern)
+            .replace("[*]", "*")
+            .replace("[?]", "?"),
+    )
+    .is_ok_and(|pattern| pattern.matches(path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_path_filter() {
+        let input = "!/CustomerPortal/apis/modules/*; /CustomerPortal/*; /pipelines/build.yml; /Directory.Build.props;";
+        let filter = PathFilter::parse(input);
+
+        assert_eq!(filter.exclude, vec!["/CustomerPortal/apis/modules/*"]);
+        assert_eq!(filter.to_string(), input);
+
+        assert!(filter.matches("CustomerPortal/web/src/app.ts"));
+        assert!(filter.matches("/pipelines/build.yml"));
+        assert!(filter.matches("Directory.Build.props"));
+        assert!(!filter.matches("Directory.Build.props.bak"));
+        assert!(!filter.matches("CustomerPortal/apis/modules/RentalModule/Api.cs"));
+        assert!(!filter.matches("pipelines/deploy.yml"));
+    }
+}
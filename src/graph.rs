@@ -0,0 +1,467 @@
+//! The project reference graph of a repository. Every project is parsed once, no matter how
+//! many pipelines or other projects reach it.
+
+use std::{
+    collections::{BTreeMap, HashSet, VecDeque},
+    path::{Path, PathBuf},
+};
+
+use rayon::prelude::*;
+
+use crate::{
+    cache::Cache, config::TestSettings, project::Project, properties::BuildContext, repo, Error,
+};
+
+#[derive(Debug)]
+enum Node {
+    Project(Project),
+    /// A referenced project file that does not exist.
+    Missing,
+    /// A project that exists but could not be loaded.
+    Failed(Error),
+}
+
+/// Projects as nodes and their references as edges. Nodes are keyed by the canonical path of
+/// the project file relative to the repository root, so that references spelled differently
+/// (`..\Shared\Shared.csproj` from one project, `../../apis/Shared/Shared.csproj` from another)
+/// end up at the same node.
+#[derive(Debug)]
+pub struct DependencyGraph {
+    repo_root: PathBuf,
+    /// `repo_root` with symbolic links resolved, to compare canonical paths against.
+    canonical_root: PathBuf,
+    context: BuildContext,
+    nodes: BTreeMap<PathBuf, Node>,
+    /// The projects each loaded project references, in the order of its references.
+    edges: BTreeMap<PathBuf, Vec<PathBuf>>,
+    cache: Option<Cache>,
+    /// Projects matching the test patterns are tagged as tests when they are loaded.
+    tests: TestSettings,
+}
+
+/// The projects reachable from a set of projects.
+#[derive(Debug, Default)]
+pub struct Closure {
+    /// The reachable projects in depth-first order, including the ones the walk started from.
+    pub projects: Vec<PathBuf>,
+    /// Missing references, projects that failed to load and reference cycles met on the way.
+    pub problems: Vec<Error>,
+}
+
+impl DependencyGraph {
+    pub fn new(repo_root: &Path, context: &BuildContext) -> Self {
+        Self {
+            repo_root: repo_root.to_path_buf(),
+            canonical_root: std::fs::canonicalize(repo_root)
+                .unwrap_or_else(|_| repo_root.to_path_buf()),
+            context: context.clone(),
+            nodes: BTreeMap::new(),
+            edges: BTreeMap::new(),
+            cache: None,
+            tests: TestSettings::default(),
+        }
+    }
+
+    /// Looks projects up in `cache` before evaluating them, and adds the evaluated ones to it.
+    pub fn with_cache(mut self, cache: Cache) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// Recognizes test projects with `tests` instead of the default patterns.
+    pub fn with_tests(mut self, tests: TestSettings) -> Self {
+        self.tests = tests;
+        self
+    }
+
+    pub fn cache(&self) -> Option<&Cache> {
+        self.cache.as_ref()
+    }
+
+    pub fn repo_root(&self) -> &Path {
+        &self.repo_root
+    }
+
+    pub fn context(&self) -> &BuildContext {
+        &self.context
+    }
+
+    /// The canonical form of a project path relative to the repository root. Paths that do not
+    /// exist are only normalized.
+    pub fn canonical(&self, path: &Path) -> PathBuf {
+        let path = repo::relative_to(&self.repo_root, path);
+        match std::fs::canonicalize(self.repo_root.join(&path)) {
+            Ok(canonical) => repo::relative_to(&self.canonical_root, &canonical),
+            Err(_) => path,
+        }
+    }
+
+    /// Loads the project at `path` and every project it references, transitively. Projects that
+    /// are already in the graph are not parsed again. Returns the key of the project.
+    pub fn add(&mut self, path: &Path) -> PathBuf {
+        self.add_all(&[path.to_path_buf()]).remove(0)
+    }
+
+    /// Like [`DependencyGraph::add`] for several projects. The projects are parsed in parallel,
+    /// one level of references at a time. Returns the keys in the order of `paths`.
+    pub fn add_all(&mut self, paths: &[PathBuf]) -> Vec<PathBuf> {
+        let keys = paths
+            .iter()
+            .map(|path| self.canonical(path))
+            .collect::<Vec<_>>();
+
+        let mut pending = keys.clone();
+        loop {
+            pending.sort();
+            pending.dedup();
+            pending.retain(|path| !self.nodes.contains_key(path));
+            if pending.is_empty() {
+                break;
+            }
+
+            let loaded = pending
+                .par_iter()
+                .map(|path| self.load(path))
+                .collect::<Vec<_>>();
+
+            pending = Vec::new();
+            for (path, mut node, cached) in loaded {
+                if let (Node::Project(project), Some(cache), false) =
+                    (&node, &mut self.cache, cached)
+                {
+                    cache.insert(&self.repo_root, &self.context, project);
+                }
+                // Tagged after caching, the patterns are not part of the cache key.
+                if let Node::Project(project) = &mut node {
+                    project.is_test |= self.tests.matches(&project.path);
+                }
+                if let Node::Project(project) = &node {
+                    let references = project
+                        .references
+                        .iter()
+                        .map(|reference| self.canonical(&reference.include_path))
+                        .collect::<Vec<_>>();
+                    pending.extend(references.iter().cloned());
+                    self.edges.insert(path.clone(), references);
+                }
+                self.nodes.insert(path, node);
+            }
+        }
+
+        keys
+    }
+
+    /// Loads a project from the cache or by evaluating it. The flag tells whether it was cached.
+    fn load(&self, path: &Path) -> (PathBuf, Node, bool) {
+        let cached = self
+            .cache
+            .as_ref()
+            .and_then(|cache| cache.get(&self.repo_root, &self.context, path));
+        if let Some(project) = cached {
+            return (path.to_path_buf(), Node::Project(project), true);
+        }
+
+        let node = if !self.repo_root.join(path).is_file() {
+            Node::Missing
+        } else {
+            match Project::new(&self.repo_root, path.to_path_buf(), &self.context) {
+                Ok(project) => Node::Project(project),
+                Err(e) => Node::Failed(e),
+            }
+        };
+
+        (path.to_path_buf(), node, false)
+    }
+
+    /// The loaded project with the given key, if it could be loaded.
+    pub fn project(&self, path: &Path) -> Option<&Project> {
+        match self.nodes.get(path) {
+            Some(Node::Project(project)) => Some(project),
+            _ => None,
+        }
+    }
+
+    /// Every loaded project, ordered by path.
+    pub fn projects(&self) -> impl Iterator<Item = &Project> {
+        self.nodes.values().filter_map(|node| match node {
+            Node::Project(project) => Some(project),
+            _ => None,
+        })
+    }
+
+    /// The keys of the projects the project `path` references directly.
+    pub fn references(&self, path: &Path) -> &[PathBuf] {
+        self.edges.get(path).map(Vec::as_slice).unwrap_or_default()
+    }
+
+    /// The shortest chain of references from one of `roots` to every loaded project reachable
+    /// from them, starting with the root and ending with the project itself. Ties go to the
+    /// earlier root and the earlier reference.
+    pub fn chains<'a>(
+        &self,
+        roots: impl IntoIterator<Item = &'a PathBuf>,
+    ) -> BTreeMap<PathBuf, Vec<PathBuf>> {
+        let mut chains = BTreeMap::new();
+        let mut queue = VecDeque::new();
+        for root in roots {
+            if self.project(root).is_some() && !chains.contains_key(root) {
+                chains.insert(root.clone(), vec![root.clone()]);
+                queue.push_back(root.clone());
+            }
+        }
+        while let Some(path) = queue.pop_front() {
+            for reference in self.references(&path) {
+                if self.project(reference).is_none() || chains.contains_key(reference) {
+                    continue;
+                }
+                let mut chain = chains[&path].clone();
+                chain.push(reference.clone());
+                chains.insert(reference.clone(), chain);
+                queue.push_back(reference.clone());
+            }
+        }
+
+        chains
+    }
+
+    /// The loaded projects that reference the project `path`, directly or transitively, with
+    /// the length of the shortest chain of references to it. Ordered by distance, then path.
+    pub fn dependents(&self, path: &Path) -> Vec<(PathBuf, usize)> {
+        let mut reverse = BTreeMap::<&Path, Vec<&Path>>::new();
+        for (from, references) in &self.edges {
+            for reference in references {
+                reverse.entry(reference).or_default().push(from);
+            }
+        }
+
+        let mut distances = BTreeMap::new();
+        let mut queue = VecDeque::from([(path, 0)]);
+        while let Some((path, distance)) = queue.pop_front() {
+            for dependent in reverse.get(path).into_iter().flatten() {
+                if *dependent != path && !distances.contains_key(dependent) {
+                    distances.insert(*dependent, distance + 1);
+                    queue.push_back((dependent, distance + 1));
+                }
+            }
+        }
+        distances.remove(path);
+
+        let mut dependents = distances
+            .into_iter()
+            .map(|(path, distance)| (path.to_path_buf(), distance))
+            .collect::<Vec<_>>();
+        dependents.sort_by_key(|(_, distance)| *distance);
+        dependents
+    }
+
+    /// Walks the graph from `roots`, which must have been added, visiting every project once.
+    pub fn closure<'a>(&self, roots: impl IntoIterator<Item = &'a PathBuf>) -> Closure {
+        let mut walk = Walk {
+            graph: self,
+            visited: HashSet::new(),
+            stack: Vec::new(),
+            closure: Closure::default(),
+        };
+        for root in roots {
+            walk.visit(root);
+        }
+
+        walk.closure
+    }
+}
+
+/// A depth-first walk that remembers the current chain of references to detect cycles.
+struct Walk<'a> {
+    graph: &'a DependencyGraph,
+    visited: HashSet<&'a Path>,
+    stack: Vec<&'a Path>,
+    closure: Closure,
+}
+
+impl<'a> Walk<'a> {
+    fn visit(&mut self, path: &'a Path) {
+        if let Some(start) = self.stack.iter().position(|entry| *entry == path) {
+            let mut cycle = self.stack[start..]
+                .iter()
+                .map(|path| path.to_path_buf())
+                .collect::<Vec<_>>();
+            cycle.push(path.to_path_buf());
+            self.closure.problems.push(Error::ReferenceCycle { cycle });
+            return;
+        }
+        if !self.visited.insert(path) {
+            return;
+        }
+
+        match self.graph.nodes.get(path) {
+            Some(Node::Project(_)) => self.closure.projects.push(path.to_path_buf()),
+            Some(Node::Failed(e)) => {
+                self.closure.problems.push(e.clone());
+                return;
+            }
+            Some(Node::Missing) | None => {
+                self.closure.problems.push(Error::MissingReference {
+                    project: self
+                        .stack
+                        .last()
+                        .map(|p| p.to_path_buf())
+                        .unwrap_or_default(),
+                    reference: path.to_path_buf(),
+                });
+                return;
+            }
+        }
+
+        self.stack.push(path);
+        for reference in self.graph.references(path) {
+            self.visit(reference);
+        }
+        self.stack.pop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::write_tree;
+
+    fn project(references: &[&str]) -> String {
+        let references = references
+            .iter()
+            .map(|reference| {
+                format!(
+                    r#"<ProjectReference Include="..\{0}\{0}.csproj" />"#,
+                    reference
+                )
+            })
+            .collect::<String>();
+        format!("<Project><ItemGroup>{}</ItemGroup></Project>", references)
+    }
+
+    #[test]
+    fn test_closure() {
+        let dir = tempfile::tempdir().unwrap();
+        // Api references Domain and Infrastructure, which both reference Core. Core and
+        // Infrastructure reference each other.
+        write_tree(
+            dir.path(),
+            &[
+                ("Api/Api.csproj", &project(&["Domain", "Infrastructure"])),
+                ("Domain/Domain.csproj", &project(&["Core"])),
+                (
+                    "Infrastructure/Infrastructure.csproj",
+                    &project(&["Core", "Missing"]),
+                ),
+                ("Core/Core.csproj", &project(&["Infrastructure"])),
+            ],
+        );
+
+        let mut graph = DependencyGraph::new(dir.path(), &BuildContext::default());
+        let api = graph.add(Path::new("Api/../Api/Api.csproj"));
+        assert_eq!(api, PathBuf::from("Api/Api.csproj"));
+        assert_eq!(graph.projects().count(), 4);
+
+        let closure = graph.closure([&api]);
+        assert_eq!(
+            closure.projects,
+            vec![
+                PathBuf::from("Api/Api.csproj"),
+                PathBuf::from("Domain/Domain.csproj"),
+                PathBuf::from("Core/Core.csproj"),
+                PathBuf::from("Infrastructure/Infrastructure.csproj"),
+            ]
+        );
+        let chains = graph.chains([&api]);
+        assert_eq!(
+            chains[Path::new("Core/Core.csproj")],
+            vec![
+                PathBuf::from("Api/Api.csproj"),
+                PathBuf::from("Domain/Domain.csproj"),
+                PathBuf::from("Core/Core.csproj"),
+            ]
+        );
+        assert_eq!(
+            chains[Path::new("Infrastructure/Infrastructure.csproj")].len(),
+            2
+        );
+
+        assert_eq!(
+            closure
+                .problems
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>(),
+            vec![
+                "reference cycle: Core/Core.csproj -> Infrastructure/Infrastructure.csproj -> Core/Core.csproj",
+                "Infrastructure/Infrastructure.csproj references Missing/Missing.csproj, which does not exist",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_dependents() {
+        let dir = tempfile::tempdir().unwrap();
+        // App references Left and Right, which both reference Base. Base and Loop reference
+        // each other, and Tool references Base but is not reached from App.
+        write_tree(
+            dir.path(),
+            &[
+                ("App/App.csproj", &project(&["Left", "Right"])),
+                ("Left/Left.csproj", &project(&["Base"])),
+                ("Right/Right.csproj", &project(&["Base"])),
+                ("Base/Base.csproj", &project(&["Loop"])),
+                ("Loop/Loop.csproj", &project(&["Base"])),
+                ("Tool/Tool.csproj", &project(&["Base"])),
+            ],
+        );
+
+        let mut graph = DependencyGraph::new(dir.path(), &BuildContext::default());
+        graph.add(Path::new("App/App.csproj"));
+        let dependents = |graph: &DependencyGraph, path: &str| graph.dependents(Path::new(path));
+        let expected = |dependents: &[(&str, usize)]| {
+            dependents
+                .iter()
+                .map(|(path, distance)| (PathBuf::from(path), *distance))
+                .collect::<Vec<_>>()
+        };
+
+        // Diamond: App is counted once, at its shortest distance.
+        assert_eq!(
+            dependents(&graph, "Left/Left.csproj"),
+            expected(&[("App/App.csproj", 1)])
+        );
+        assert_eq!(
+            dependents(&graph, "Base/Base.csproj"),
+            expected(&[
+                ("Left/Left.csproj", 1),
+                ("Loop/Loop.csproj", 1),
+                ("Right/Right.csproj", 1),
+                ("App/App.csproj", 2),
+            ])
+        );
+        // Cycle: Base depends on Loop and the walk ends without listing Loop itself.
+        assert_eq!(
+            dependents(&graph, "Loop/Loop.csproj"),
+            expected(&[
+                ("Base/Base.csproj", 1),
+                ("Left/Left.csproj", 2),
+                ("Right/Right.csproj", 2),
+                ("App/App.csproj", 3),
+            ])
+        );
+        assert_eq!(dependents(&graph, "App/App.csproj"), expected(&[]));
+
+        // Projects only count once they are loaded, like the ones `rdeps` adds.
+        graph.add(Path::new("Tool/Tool.csproj"));
+        assert_eq!(
+            dependents(&graph, "Base/Base.csproj"),
+            expected(&[
+                ("Left/Left.csproj", 1),
+                ("Loop/Loop.csproj", 1),
+                ("Right/Right.csproj", 1),
+                ("Tool/Tool.csproj", 1),
+                ("App/App.csproj", 2),
+            ])
+        );
+    }
+}
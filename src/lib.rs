@@ -5,8 +5,29 @@ use nom::{
     IResult,
 };
 
+pub mod cache;
+pub mod condition;
+pub mod config;
+pub mod definition;
+pub mod discovery;
+mod error;
+pub mod evaluation;
+pub mod export;
+pub mod filter;
+pub mod graph;
+pub mod msbuild;
 pub mod pipeline;
 pub mod project;
+pub mod properties;
+pub mod repo;
+pub mod solution;
+pub mod template;
+#[cfg(test)]
+mod testing;
+pub mod trigger;
+pub mod variables;
+
+pub use error::{Error, Result};
 
 fn fenced<'a>(start: &'a str, end: &'a str) -> impl FnMut(&'a str) -> IResult<&'a str, &'a str> {
     map(tuple((tag(start), take_until(end), tag(end))), |x| x.1)
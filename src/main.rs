@@ -1,36 +1,336 @@
-use std::path::PathBuf;
+use std::{
+    io::{ErrorKind, Write},
+    path::{Path, PathBuf},
+};
 
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use console::style;
-use rami_api_deps::pipeline::Pipeline;
-use walkdir::{DirEntry, WalkDir};
+use rami_api_deps::{
+    cache::Cache,
+    config::{Config, CONFIG_FILE_NAME},
+    discovery,
+    export::Export,
+    filter::PathFilter,
+    graph::DependencyGraph,
+    pipeline::{self, Pipeline, Reason},
+    project::Project,
+    properties::BuildContext,
+    repo, Error, Result,
+};
+use serde::Serialize;
 
 /// Generate dependency things!
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 struct Args {
     /// Root directory to search from
-    #[arg(short, long, default_value = ".")]
+    #[arg(global = true, short, long, default_value = ".")]
     root_dir: PathBuf,
+
+    /// Root of the repository, paths in the path filters are relative to it. Defaults to the
+    /// nearest directory above the root directory that contains `.git`
+    #[arg(global = true, long)]
+    repo_root: Option<PathBuf>,
+
+    /// Build configuration that project conditions are evaluated for
+    #[arg(global = true, short, long, default_value = "Release")]
+    configuration: String,
+
+    /// Build platform that project conditions are evaluated for
+    #[arg(global = true, short, long, default_value = "AnyCPU")]
+    platform: String,
+
+    /// Number of threads to scan and parse with, 0 uses one per CPU core
+    #[arg(global = true, short, long, default_value_t = 0)]
+    jobs: usize,
+
+    /// Do not read or write the cache of parsed projects in `.rami-api-deps`
+    #[arg(global = true, long)]
+    no_cache: bool,
+
+    /// Keep test projects in pipelines and their path filters, tagged as tests, instead of
+    /// leaving them out. Also set by `include = true` in the `[tests]` section of the config
+    #[arg(global = true, long)]
+    include_tests: bool,
+
+    /// Skip files and directories matching this glob, relative to the repository root, on top
+    /// of `.gitignore` files and build output like `bin`, `obj` and `node_modules`. Can be
+    /// repeated, adds to `exclude` in the `[discovery]` section of the config
+    #[arg(global = true, long, value_name = "GLOB", value_parser = parse_glob)]
+    exclude: Vec<String>,
+
+    #[command(flatten)]
+    generate: GenerateArgs,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(clap::Args, Debug, Clone)]
+struct GenerateArgs {
+    /// Only compare the computed path filters with the `.azure-pathfilter` files on disk, print
+    /// a diff for each stale one and exit with an error if there are any
+    #[arg(long)]
+    check: bool,
+
+    /// Write the path filters into the `trigger` and `pr` sections of the pipeline files
+    /// instead of `.azure-pathfilter` files, keeping the rest of each file as it is
+    #[arg(long, conflicts_with = "check")]
+    apply: bool,
+
+    /// Output format. `json` prints the analysis of every pipeline to stdout instead of writing
+    /// path filters. The cache of parsed projects is still updated
+    #[arg(long, value_enum, default_value_t = Format::Text, conflicts_with_all = ["check", "apply"])]
+    format: Format,
+}
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq)]
+enum Format {
+    Text,
+    Json,
+}
+
+/// A pipeline as printed by `--format json`.
+#[derive(Serialize)]
+struct PipelineOutput<'a> {
+    #[serde(flatten)]
+    pipeline: &'a Pipeline,
+    /// The path filter as written to `.azure-pathfilter`.
+    filter: PathFilter,
+    problems: Vec<String>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Write a `.azure-pathfilter` next to every pipeline (the default), or a
+    /// `<name>.azure-pathfilter` for pipeline files not named `azure-pipelines.yml`
+    Generate(GenerateArgs),
+
+    /// List the pipelines triggered by a set of changed files, according to the path filters and
+    /// according to the dependency graph
+    Affected {
+        /// File listing the changed paths, one per line, relative to the repository root. Read
+        /// from stdin when neither this nor `--base` is given
+        file: Option<PathBuf>,
+
+        /// Use the files that differ between this git revision and the working tree
+        #[arg(long, conflicts_with = "file")]
+        base: Option<String>,
+    },
+
+    /// Print the project reference graph, with pipelines pointing at the projects they build
+    Graph {
+        /// Only draw this pipeline, by name or path, and the projects it depends on
+        #[arg(long)]
+        pipeline: Option<String>,
+
+        #[arg(long, value_enum, default_value_t = GraphFormat::Dot)]
+        format: GraphFormat,
+    },
+
+    /// Explain why a pipeline depends on a file or project, with the shortest chain of
+    /// references from one of the projects it builds
+    Why {
+        /// Name or path of the pipeline
+        pipeline: String,
+
+        /// A path relative to the current directory or the repository root, or a project name
+        target: String,
+    },
+
+    /// List the projects and pipelines that depend on a project, directly or transitively
+    Rdeps {
+        /// A project file or a path inside a project directory, relative to the current
+        /// directory or the repository root, or a project name
+        target: String,
+    },
+
+    /// Manage the cache of parsed projects
+    Cache {
+        #[command(subcommand)]
+        command: CacheCommand,
+    },
+}
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq)]
+enum GraphFormat {
+    /// Graphviz, e.g. `rami-api-deps graph | dot -Tsvg > graph.svg`
+    Dot,
+    Mermaid,
+}
+
+#[derive(Subcommand, Debug)]
+enum CacheCommand {
+    /// Delete the cache
+    Clear,
 }
 
-// clear; cargo run -- --root-dir C:\Users\pbac\Dev\ramirent\SE-CustomerPortal
 fn main() {
-    let args = Args::parse();
+    let mut args = Args::parse();
 
     // cwd + root_dir
-    let root_dir = std::env::current_dir().unwrap().join(args.root_dir);
+    let cwd = std::env::current_dir().unwrap_or_else(|e| {
+        report_problem(&format!("failed to read the current directory: {}", e));
+        std::process::exit(1);
+    });
+    let root_dir = repo::normalize(&cwd.join(&args.root_dir));
+    let repo_root = match &args.repo_root {
+        Some(repo_root) => repo::normalize(&cwd.join(repo_root)),
+        None => repo::find_root(&root_dir).unwrap_or_else(|| {
+            eprintln!(
+                "{} no git repository found above {}, pass --repo-root explicitly.",
+                style("Error:").red().bold(),
+                root_dir.display()
+            );
+            std::process::exit(1);
+        }),
+    };
 
-    let pipeline_walker = WalkDir::new(root_dir)
-        .into_iter()
-        // Filter out any non-accessible files
-        .filter_map(|e| e.ok())
-        // Only include pipeline files
-        .filter(is_pipeline_file);
+    if let Err(e) = rayon::ThreadPoolBuilder::new()
+        .num_threads(args.jobs)
+        .build_global()
+    {
+        report_problem(&format!("failed to start {} threads: {}", args.jobs, e));
+        std::process::exit(1);
+    }
+
+    let command = args
+        .command
+        .take()
+        .unwrap_or_else(|| Command::Generate(args.generate.clone()));
+    match command {
+        Command::Generate(options) => generate(&args, &options, &root_dir, &repo_root),
+        Command::Affected { file, base } => {
+            let changed = match (file, base) {
+                (_, Some(base)) => repo::changed_files(&repo_root, &base),
+                (Some(file), None) => std::fs::read_to_string(&file)
+                    .map(|contents| parse_changed_files(&contents))
+                    .map_err(|e| Error::io(&file, e)),
+                (None, None) => std::io::read_to_string(std::io::stdin())
+                    .map(|contents| parse_changed_files(&contents))
+                    .map_err(|e| Error::io(Path::new("<stdin>"), e)),
+            };
+            match changed {
+                Ok(changed) => affected(&args, &root_dir, &repo_root, &changed),
+                Err(e) => {
+                    report_problem(&e);
+                    std::process::exit(1);
+                }
+            }
+        }
+        Command::Graph { pipeline, format } => {
+            graph(&args, &root_dir, &repo_root, pipeline.as_deref(), format)
+        }
+        Command::Why { pipeline, target } => {
+            why(&args, &cwd, &root_dir, &repo_root, &pipeline, &target)
+        }
+        Command::Rdeps { target } => rdeps(&args, &cwd, &root_dir, &repo_root, &target),
+        Command::Cache {
+            command: CacheCommand::Clear,
+        } => {
+            if let Err(e) = Cache::clear(&repo_root) {
+                report_problem(&e);
+                std::process::exit(1);
+            }
+            println!("{}", style("Cache cleared.").green().bold());
+        }
+    }
+}
+
+/// Loads `rami-deps.toml` with the command line options applied on top.
+fn load_config(args: &Args, repo_root: &Path) -> Config {
+    let mut config = Config::load(repo_root).unwrap_or_else(|e| {
+        report_problem(&e);
+        std::process::exit(1);
+    });
+    config.tests.include |= args.include_tests;
+    config
+        .discovery
+        .exclude
+        .extend(args.exclude.iter().cloned());
+
+    config
+}
+
+fn parse_glob(pattern: &str) -> std::result::Result<String, globset::Error> {
+    globset::Glob::new(pattern).map(|_| pattern.to_string())
+}
+
+/// Finds and reads every pipeline below `root_dir` as configured by `config`, together with the
+/// graph of their projects.
+fn load_pipelines(
+    args: &Args,
+    config: &Config,
+    root_dir: &Path,
+    repo_root: &Path,
+) -> (DependencyGraph, Vec<Result<Pipeline>>) {
+    let context = BuildContext {
+        configuration: args.configuration.clone(),
+        platform: args.platform.clone(),
+    };
+
+    let pipeline_paths =
+        discovery::find_pipelines(repo_root, root_dir, &config.discovery, args.jobs);
+
+    // Shared by all pipelines, so every project is only parsed once.
+    let mut graph = DependencyGraph::new(repo_root, &context).with_tests(config.tests.clone());
+    if !args.no_cache {
+        graph = graph.with_cache(Cache::load(repo_root));
+    }
+    let pipelines = Pipeline::load_all(&mut graph, &pipeline_paths, config);
+    check_names(&pipelines);
+
+    (graph, pipelines)
+}
+
+/// Pipelines are looked up by name, so pipelines sharing one are rejected rather than one of
+/// them being picked.
+fn check_names(pipelines: &[Result<Pipeline>]) {
+    let duplicates = pipeline::duplicate_names(pipelines);
+    for (name, paths) in &duplicates {
+        report_problem(&format!(
+            "pipelines {} are all named {}, name them apart with `name` in {}",
+            paths
+                .iter()
+                .map(|path| repo::azure_path(path))
+                .collect::<Vec<_>>()
+                .join(", "),
+            name,
+            CONFIG_FILE_NAME
+        ));
+    }
+    if !duplicates.is_empty() {
+        std::process::exit(1);
+    }
+}
+
+fn generate(args: &Args, options: &GenerateArgs, root_dir: &Path, repo_root: &Path) {
+    let (graph, loaded) = load_pipelines(args, &load_config(args, repo_root), root_dir, repo_root);
+    if options.format == Format::Json {
+        json(&graph, loaded, repo_root);
+        return;
+    }
 
     let mut pipelines = Vec::new();
-    for entry in pipeline_walker {
-        let pipeline = Pipeline::new(entry.path().to_path_buf());
+    let mut problem_count = 0;
+    for pipeline in loaded {
+        let pipeline = match pipeline {
+            Ok(pipeline) => pipeline,
+            Err(e) => {
+                report_problem(&e);
+                problem_count += 1;
+                println!();
+                continue;
+            }
+        };
+        for problem in &pipeline.problems {
+            report_problem(problem);
+        }
+        problem_count += pipeline.problems.len();
+        if options.check {
+            pipelines.push(pipeline);
+            continue;
+        }
 
         println!(
             "Pipeline {}, includes {} project{}.",
@@ -48,13 +348,6 @@ fn main() {
                 pipeline
                     .projects
                     .iter()
-                    // .map(|p| p
-                    //     .path
-                    //     .file_name()
-                    //     .unwrap()
-                    //     .to_str()
-                    //     .unwrap()
-                    //     .trim_end_matches(".csproj"))
                     .map(|p| p.to_string())
                     .collect::<Vec<String>>()
                     .join(", ")
@@ -74,30 +367,504 @@ fn main() {
         pipelines.push(pipeline);
     }
 
-    // create path filter files next to the pipeline files
+    if options.check {
+        check(&pipelines, problem_count);
+        return;
+    }
+
+    if options.apply {
+        println!(
+            "{}",
+            style("Updating pipeline triggers...").magenta().bold()
+        );
+        for pipeline in pipelines {
+            let name = style(&pipeline.name).green().italic().bold();
+            // A path filter computed from partial data would drop paths the pipeline needs.
+            if !pipeline.problems.is_empty() {
+                println!(
+                    "Pipeline {}: not updated because of the problems above.",
+                    name
+                );
+                continue;
+            }
+            match pipeline.apply_path_filter() {
+                Ok(true) => println!(
+                    "Pipeline {}: updated {}.",
+                    name,
+                    style(repo::azure_path(&pipeline.path)).yellow()
+                ),
+                Ok(false) => {}
+                Err(e) => {
+                    report_problem(&e);
+                    problem_count += 1;
+                }
+            }
+        }
+    } else {
+        // create path filter files next to the pipeline files
+        println!(
+            "{}",
+            style("Creating path filter files...").magenta().bold()
+        );
+        for pipeline in pipelines {
+            let file = path_filter_file(repo_root, &pipeline);
+            if let Err(e) = std::fs::write(&file, pipeline.complete_path_filter()) {
+                report_problem(&format!("failed to write {}: {}", file.display(), e));
+                problem_count += 1;
+            }
+        }
+    }
+    if let Some(Err(e)) = graph.cache().map(|cache| cache.save(repo_root)) {
+        report_problem(&e);
+        problem_count += 1;
+    }
+    if options.apply {
+        println!(
+            "{} {}",
+            style("Done!").green().bold(),
+            style("The pipeline triggers are up to date.").italic()
+        );
+    } else {
+        println!(
+            "{} {}",
+            style("Done!").green().bold(),
+            style("Now it's time to paste the path filters into Azure DevOps.").italic()
+        );
+    }
+
+    if problem_count > 0 {
+        eprintln!(
+            "{} {} problem{} found, the path filters may be incomplete.",
+            style("Error:").red().bold(),
+            problem_count,
+            if problem_count == 1 { "" } else { "s" }
+        );
+        std::process::exit(1);
+    }
+}
+
+/// Prints every pipeline as JSON. Problems are included per pipeline and also reported on
+/// stderr, pipelines that could not be read at all only on stderr.
+fn json(graph: &DependencyGraph, loaded: Vec<Result<Pipeline>>, repo_root: &Path) {
+    let mut problem_count = 0;
+    let mut pipelines = Vec::new();
+    for pipeline in loaded {
+        match pipeline {
+            Ok(pipeline) => pipelines.push(pipeline),
+            Err(e) => {
+                report_problem(&e);
+                problem_count += 1;
+            }
+        }
+    }
+
+    let output = pipelines
+        .iter()
+        .map(|pipeline| {
+            for problem in &pipeline.problems {
+                report_problem(problem);
+            }
+            problem_count += pipeline.problems.len();
+            PipelineOutput {
+                pipeline,
+                filter: pipeline.path_filter(),
+                problems: pipeline.problems.iter().map(ToString::to_string).collect(),
+            }
+        })
+        .collect::<Vec<_>>();
+    let mut stdout = std::io::stdout().lock();
+    let written = serde_json::to_writer_pretty(&mut stdout, &output)
+        .map_err(std::io::Error::from)
+        .and_then(|()| writeln!(stdout));
+    match written {
+        // The reader stopped early, e.g. `rami-api-deps --format json | head`.
+        Err(e) if e.kind() == ErrorKind::BrokenPipe => {}
+        Err(e) => {
+            report_problem(&format!("failed to print the pipelines: {}", e));
+            problem_count += 1;
+        }
+        Ok(()) => {}
+    }
+
+    if let Some(Err(e)) = graph.cache().map(|cache| cache.save(repo_root)) {
+        report_problem(&e);
+        problem_count += 1;
+    }
+    if problem_count > 0 {
+        std::process::exit(1);
+    }
+}
+
+/// Compares the path filters with the files on disk without writing anything, and exits with an
+/// error if any of them is stale or there were problems.
+fn check(pipelines: &[Pipeline], mut problem_count: usize) {
+    let mut stale = 0;
+    for pipeline in pipelines {
+        let diff = match pipeline.path_filter_diff() {
+            Ok(Some(diff)) => diff,
+            Ok(None) => continue,
+            Err(e) => {
+                report_problem(&e);
+                problem_count += 1;
+                continue;
+            }
+        };
+        stale += 1;
+
+        println!(
+            "Pipeline {}: {} is out of date.",
+            style(&pipeline.name).green().italic().bold(),
+            style(repo::azure_path(&pipeline.filter_file)).yellow()
+        );
+        for line in diff.lines() {
+            match line.chars().next() {
+                Some('+') if !line.starts_with("+++") => println!("{}", style(line).green()),
+                Some('-') if !line.starts_with("---") => println!("{}", style(line).red()),
+                _ => println!("{}", line),
+            }
+        }
+        println!();
+    }
+
+    if stale > 0 {
+        eprintln!(
+            "{} {} path filter file{} out of date, run rami-api-deps to regenerate {}.",
+            style("Error:").red().bold(),
+            stale,
+            if stale == 1 { " is" } else { "s are" },
+            if stale == 1 { "it" } else { "them" }
+        );
+    } else {
+        println!(
+            "{}",
+            style("All path filter files are up to date.")
+                .green()
+                .bold()
+        );
+    }
+    if stale > 0 || problem_count > 0 {
+        std::process::exit(1);
+    }
+}
+
+fn path_filter_file(repo_root: &Path, pipeline: &Pipeline) -> PathBuf {
+    repo_root.join(&pipeline.filter_file)
+}
+
+/// Whether `name` is the name of `pipeline` or the path of its file.
+fn is_named(pipeline: &Pipeline, name: &str) -> bool {
+    pipeline.name == name || pipeline.path == repo::normalize(Path::new(name))
+}
+
+fn graph(args: &Args, root_dir: &Path, repo_root: &Path, name: Option<&str>, format: GraphFormat) {
+    let (graph, loaded) = load_pipelines(args, &load_config(args, repo_root), root_dir, repo_root);
+    let mut pipelines = Vec::new();
+    for pipeline in loaded {
+        match pipeline {
+            Ok(pipeline) => pipelines.push(pipeline),
+            Err(e) => report_problem(&e),
+        }
+    }
+
+    let export = match name {
+        Some(name) => match pipelines.iter().find(|pipeline| is_named(pipeline, name)) {
+            Some(pipeline) => Export::pipeline(&graph, pipeline),
+            None => {
+                report_problem(&format!("no pipeline named {}", name));
+                std::process::exit(1);
+            }
+        },
+        None => Export::new(&graph, &pipelines),
+    };
+    match format {
+        GraphFormat::Dot => print!("{}", export.to_dot()),
+        GraphFormat::Mermaid => print!("{}", export.to_mermaid()),
+    }
+
+    if let Some(Err(e)) = graph.cache().map(|cache| cache.save(repo_root)) {
+        report_problem(&e);
+    }
+}
+
+fn why(args: &Args, cwd: &Path, root_dir: &Path, repo_root: &Path, name: &str, target: &str) {
+    let (_, loaded) = load_pipelines(args, &load_config(args, repo_root), root_dir, repo_root);
+    let Some(pipeline) = loaded
+        .into_iter()
+        .flatten()
+        .find(|pipeline| is_named(pipeline, name))
+    else {
+        report_problem(&format!("no pipeline named {}", name));
+        std::process::exit(1);
+    };
+
+    let path = resolve_target(
+        cwd,
+        repo_root,
+        target,
+        pipeline.projects.iter().chain(&pipeline.dependencies),
+    );
+
+    let pipeline_name = style(&pipeline.name).green().italic().bold();
+    let target = style(repo::azure_path(&path)).yellow();
+    let chain = match pipeline.explain(&path) {
+        None => {
+            println!("Pipeline {} does not depend on {}.", pipeline_name, target);
+            return;
+        }
+        Some(Reason::PipelineFile) => {
+            println!("{} is the pipeline file of {}.", target, pipeline_name);
+            return;
+        }
+        Some(Reason::Template) => {
+            println!("{} is a template included by {}.", target, pipeline_name);
+            return;
+        }
+        Some(Reason::Solution) => {
+            println!("{} is a solution built by {}.", target, pipeline_name);
+            return;
+        }
+        Some(Reason::Config) => {
+            println!(
+                "Pipeline {} depends on {}, which is included in {}.",
+                pipeline_name, target, CONFIG_FILE_NAME
+            );
+            return;
+        }
+        Some(Reason::Project(chain)) => {
+            let project = chain.last().cloned().unwrap_or_default();
+            if project == path {
+                println!("Pipeline {} depends on {}:", pipeline_name, target);
+            } else {
+                println!(
+                    "Pipeline {} depends on {}, which is in the directory of {}:",
+                    pipeline_name,
+                    target,
+                    style(repo::azure_path(&project)).cyan()
+                );
+            }
+            chain
+        }
+        Some(Reason::Import(chain)) => {
+            println!(
+                "Pipeline {} depends on {}, which is imported by {}:",
+                pipeline_name,
+                target,
+                style(repo::azure_path(chain.last().map_or(Path::new(""), |p| p))).cyan()
+            );
+            chain
+        }
+    };
+    for (i, project) in chain.iter().enumerate() {
+        let arrow = if i == 0 { "   " } else { "-> " };
+        println!(
+            "    {}{}",
+            arrow,
+            style(repo::azure_path(project)).magenta()
+        );
+    }
+}
+
+fn rdeps(args: &Args, cwd: &Path, root_dir: &Path, repo_root: &Path, target: &str) {
+    let config = load_config(args, repo_root);
+    let (mut graph, loaded) = load_pipelines(args, &config, root_dir, repo_root);
+    // Projects no pipeline builds can still depend on the target.
+    graph.add_all(&discovery::find_projects(
+        repo_root,
+        root_dir,
+        &config.discovery,
+        args.jobs,
+    ));
+
+    let path = resolve_target(cwd, repo_root, target, graph.projects());
+    let owner = graph
+        .projects()
+        .map(|project| (project, project.path.parent().unwrap_or(Path::new(""))))
+        .filter(|(_, directory)| path.starts_with(directory))
+        .max_by_key(|(_, directory)| directory.components().count())
+        .map(|(project, _)| project.path.clone());
+    let Some(project) = owner else {
+        report_problem(&format!(
+            "{} is not a project or inside the directory of one",
+            repo::azure_path(&path)
+        ));
+        std::process::exit(1);
+    };
+
     println!(
-        "{}",
-        style("Creating path filter files...").magenta().bold()
+        "Project {}",
+        style(repo::azure_path(&project)).cyan().bold()
     );
-    for pipeline in pipelines {
-        let output_dir = pipeline.path.parent().unwrap();
-        let file = output_dir.join(".azure-pathfilter");
+    let dependents = graph.dependents(&project);
+    println!(
+        "Referenced by {} project{}:",
+        style(dependents.len()).yellow().bold(),
+        if dependents.len() == 1 { "" } else { "s" }
+    );
+    for (dependent, distance) in &dependents {
+        if *distance == 1 {
+            println!("    {}", style(repo::azure_path(dependent)).magenta());
+        } else {
+            println!(
+                "    {} {}",
+                style(repo::azure_path(dependent)).magenta(),
+                style(format!("(through {} references)", distance)).dim()
+            );
+        }
+    }
+
+    let pipelines = loaded
+        .into_iter()
+        .filter_map(|pipeline| match pipeline {
+            Ok(pipeline) => Some(pipeline),
+            Err(e) => {
+                report_problem(&e);
+                None
+            }
+        })
+        .filter(|pipeline| pipeline.reference_chain(&project).is_some())
+        .collect::<Vec<_>>();
+    println!(
+        "Included in {} pipeline{}:",
+        style(pipelines.len()).yellow().bold(),
+        if pipelines.len() == 1 { "" } else { "s" }
+    );
+    for pipeline in &pipelines {
+        print!(
+            "    {} {}",
+            style(&pipeline.name).green().italic().bold(),
+            style(repo::azure_path(&pipeline.path)).dim()
+        );
+        if pipeline.environments.is_empty() {
+            println!();
+        } else {
+            println!(
+                ", deploys to {}",
+                style(pipeline.environments.join(", ")).yellow()
+            );
+        }
+    }
+
+    if let Some(Err(e)) = graph.cache().map(|cache| cache.save(repo_root)) {
+        report_problem(&e);
+    }
+}
+
+/// Turns a command line argument into a path relative to the repository root: an existing
+/// path, otherwise the name of one of `projects`, otherwise a path relative to the repository
+/// root.
+fn resolve_target<'a>(
+    cwd: &Path,
+    repo_root: &Path,
+    target: &str,
+    mut projects: impl Iterator<Item = &'a Project>,
+) -> PathBuf {
+    if cwd.join(target).exists() {
+        return repo::relative_to(repo_root, &repo::normalize(&cwd.join(target)));
+    }
 
-        let mut path_filter = pipeline.complete_path_filter();
-        if pipeline.name == "CustomerPortal" {
-            path_filter = format!("/CustomerPortal/*; {}", path_filter);
+    projects
+        .find(|project| project.name().eq_ignore_ascii_case(target))
+        .map(|project| project.path.clone())
+        .unwrap_or_else(|| PathBuf::from(target.replace('\\', "/").trim_start_matches('/')))
+}
+
+fn parse_changed_files(contents: &str) -> Vec<PathBuf> {
+    contents
+        .lines()
+        .map(|line| line.trim().replace('\\', "/"))
+        .filter(|line| !line.is_empty())
+        .map(|line| PathBuf::from(line.trim_start_matches('/')))
+        .collect()
+}
+
+fn affected(args: &Args, root_dir: &Path, repo_root: &Path, changed: &[PathBuf]) {
+    let (_, loaded) = load_pipelines(args, &load_config(args, repo_root), root_dir, repo_root);
+
+    let mut affected = 0;
+    let mut disagreements = 0;
+    for pipeline in loaded {
+        let pipeline = match pipeline {
+            Ok(pipeline) => pipeline,
+            Err(e) => {
+                report_problem(&e);
+                continue;
+            }
+        };
+        for problem in &pipeline.problems {
+            report_problem(problem);
         }
 
-        std::fs::write(file, path_filter).unwrap();
+        let filter = pipeline.path_filter();
+        let by_filter = changed
+            .iter()
+            .filter(|path| filter.matches(&path.to_string_lossy()))
+            .collect::<Vec<_>>();
+        let by_graph = changed
+            .iter()
+            .filter(|path| pipeline.depends_on(path))
+            .collect::<Vec<_>>();
+        if by_filter.is_empty() && by_graph.is_empty() {
+            continue;
+        }
+        affected += 1;
+
+        // The changes only one side matches, even if both sides match something.
+        let only_filter = by_filter
+            .iter()
+            .filter(|path| !by_graph.contains(path))
+            .collect::<Vec<_>>();
+        let only_graph = by_graph
+            .iter()
+            .filter(|path| !by_filter.contains(path))
+            .collect::<Vec<_>>();
+
+        let name = style(&pipeline.name).green().italic().bold();
+        match (by_filter.is_empty(), by_graph.is_empty()) {
+            _ if only_filter.is_empty() && only_graph.is_empty() => {
+                println!("Pipeline {} is triggered.", name);
+                continue;
+            }
+            (false, true) => println!(
+                "Pipeline {} is {}, but depends on none of the changes:",
+                name,
+                style("triggered by its path filter").yellow().bold()
+            ),
+            (true, _) => println!(
+                "Pipeline {} is {}, but depends on:",
+                name,
+                style("not triggered by its path filter").red().bold()
+            ),
+            (false, false) => println!(
+                "Pipeline {} is {}, but its path filter and dependencies disagree on:",
+                name,
+                style("triggered").yellow().bold()
+            ),
+        }
+        disagreements += 1;
+        for path in only_filter {
+            println!(
+                "    {} (only matched by the path filter)",
+                style(repo::azure_path(path)).dim()
+            );
+        }
+        for path in only_graph {
+            println!(
+                "    {} (only a dependency)",
+                style(repo::azure_path(path)).dim()
+            );
+        }
     }
+
+    println!();
     println!(
-        "{} {}",
-        style("Done!").green().bold(),
-        style("Now it's time to paste the path filters into Azure DevOps.").italic()
+        "{} pipeline{} affected, {} disagreement{} between the path filters and the dependency graph.",
+        style(affected).yellow().bold(),
+        if affected == 1 { "" } else { "s" },
+        style(disagreements).yellow().bold(),
+        if disagreements == 1 { "" } else { "s" }
     );
 }
 
-fn is_pipeline_file(entry: &DirEntry) -> bool {
-    entry.file_type().is_file()
-        && entry.path().file_name().unwrap_or_default() == "azure-pipelines.yml"
+fn report_problem(problem: &dyn std::fmt::Display) {
+    eprintln!("{} {}", style("Error:").red().bold(), problem);
 }
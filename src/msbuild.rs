@@ -0,0 +1,237 @@
+//! Reading of MSBuild project files (`.csproj`, `.props`, `.targets`) into the handful of
+//! elements the dependency tracking cares about.
+
+use std::collections::BTreeMap;
+
+use roxmltree::{Document, Node};
+
+use crate::properties::Properties;
+
+/// The property groups, imports and item groups of an MSBuild file.
+#[derive(Debug, Default, Clone)]
+pub struct ProjectFile {
+    /// Property groups and imports in document order, which is the order MSBuild evaluates them.
+    pub elements: Vec<Element>,
+    pub item_groups: Vec<ItemGroup>,
+}
+
+#[derive(Debug, Clone)]
+pub enum Element {
+    PropertyGroup(PropertyGroup),
+    Import(Import),
+}
+
+/// An `<Import Project="..." />`, imports of SDKs are not included.
+#[derive(Debug, Default, Clone)]
+pub struct Import {
+    pub project: String,
+    pub condition: Option<String>,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct PropertyGroup {
+    pub condition: Option<String>,
+    pub properties: Vec<Property>,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct Property {
+    pub name: String,
+    /// The unevaluated value, property references are expanded during evaluation.
+    pub value: String,
+    pub condition: Option<String>,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct ItemGroup {
+    pub condition: Option<String>,
+    pub items: Vec<Item>,
+}
+
+/// A single item such as `<ProjectReference Include="..." />`.
+#[derive(Debug, Default, Clone)]
+pub struct Item {
+    /// The item type, i.e. the element name.
+    pub kind: String,
+    pub include: String,
+    pub condition: Option<String>,
+    /// Metadata given either as attributes or as child elements, keyed by name.
+    pub metadata: BTreeMap<String, String>,
+}
+
+impl Item {
+    /// The individual entries of the `;` separated `Include`, after property expansion.
+    pub fn includes(&self, properties: &Properties) -> Vec<String> {
+        properties
+            .expand(&self.include)
+            .split(';')
+            .map(str::trim)
+            .filter(|include| !include.is_empty())
+            .map(str::to_string)
+            .collect()
+    }
+
+    pub fn metadata(&self, name: &str) -> Option<&str> {
+        find_metadata(&self.metadata, name)
+    }
+}
+
+/// Looks up metadata by name, ignoring case like MSBuild does.
+pub(crate) fn find_metadata<'a>(
+    metadata: &'a BTreeMap<String, String>,
+    name: &str,
+) -> Option<&'a str> {
+    metadata
+        .iter()
+        .find(|(key, _)| key.eq_ignore_ascii_case(name))
+        .map(|(_, value)| value.as_str())
+}
+
+impl ProjectFile {
+    pub fn parse(contents: &str) -> Result<Self, roxmltree::Error> {
+        // A byte order mark is common in files written by Visual Studio.
+        let document = Document::parse(contents.trim_start_matches('\u{feff}'))?;
+
+        let mut project_file = ProjectFile::default();
+        for node in document.root_element().children().filter(Node::is_element) {
+            match node.tag_name().name() {
+                "PropertyGroup" => {
+                    project_file
+                        .elements
+                        .push(Element::PropertyGroup(PropertyGroup {
+                            condition: attribute(&node, "Condition"),
+                            properties: node
+                                .children()
+                                .filter(Node::is_element)
+                                .map(|node| Property {
+                                    name: node.tag_name().name().to_string(),
+                                    value: node.text().unwrap_or_default().trim().to_string(),
+                                    condition: attribute(&node, "Condition"),
+                                })
+                                .collect(),
+                        }))
+                }
+                "Import" => project_file
+                    .elements
+                    .extend(read_import(&node, None).map(Element::Import)),
+                "ImportGroup" => {
+                    let condition = attribute(&node, "Condition");
+                    project_file.elements.extend(
+                        node.children()
+                            .filter(|node| node.tag_name().name() == "Import")
+                            .filter_map(|node| read_import(&node, condition.as_deref()))
+                            .map(Element::Import),
+                    );
+                }
+                "ItemGroup" => project_file.item_groups.push(ItemGroup {
+                    condition: attribute(&node, "Condition"),
+                    items: node
+                        .children()
+                        .filter(Node::is_element)
+                        .filter_map(|node| read_item(&node))
+                        .collect(),
+                }),
+                _ => {}
+            }
+        }
+
+        Ok(project_file)
+    }
+
+    /// Every item of the given type, regardless of which item group it is in.
+    pub fn items<'a>(&'a self, kind: &'a str) -> impl Iterator<Item = (&'a ItemGroup, &'a Item)> {
+        self.item_groups.iter().flat_map(move |item_group| {
+            item_group
+                .items
+                .iter()
+                .filter(move |item| item.kind == kind)
+                .map(move |item| (item_group, item))
+        })
+    }
+}
+
+fn read_import(node: &Node, group_condition: Option<&str>) -> Option<Import> {
+    let project = attribute(node, "Project")?;
+    if attribute(node, "Sdk").is_some() {
+        return None;
+    }
+
+    let condition = match (group_condition, attribute(node, "Condition")) {
+        (Some(group), Some(own)) => Some(format!("({}) and ({})", group, own)),
+        (group, own) => own.or(group.map(str::to_string)),
+    };
+
+    Some(Import { project, condition })
+}
+
+fn read_item(node: &Node) -> Option<Item> {
+    // Items that only `Update` or `Remove` existing ones do not add anything.
+    let include = attribute(node, "Include")?;
+
+    let mut metadata = BTreeMap::new();
+    for attr in node.attributes() {
+        if !matches!(attr.name(), "Include" | "Exclude" | "Condition") {
+            metadata.insert(attr.name().to_string(), attr.value().to_string());
+        }
+    }
+    for child in node.children().filter(Node::is_element) {
+        metadata.insert(
+            child.tag_name().name().to_string(),
+            child.text().unwrap_or_default().trim().to_string(),
+        );
+    }
+
+    Some(Item {
+        kind: node.tag_name().name().to_string(),
+        include,
+        condition: attribute(node, "Condition"),
+        metadata,
+    })
+}
+
+fn attribute(node: &Node, name: &str) -> Option<String> {
+    node.attributes()
+        .find(|attr| attr.name().eq_ignore_ascii_case(name))
+        .map(|attr| attr.value().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_items() {
+        let input = r#"<?xml version="1.0" encoding="utf-8"?>
+<Project xmlns="http://schemas.microsoft.com/developer/msbuild/2003">
+  <ItemGroup Condition="'$(Configuration)' == 'Debug'">
+    <ProjectReference
+        Condition="'$(TargetFramework)' == 'net7.0'"
+        Include='..\Shared\Shared.csproj;..\Common\Common.csproj'>
+      <PrivateAssets>all</PrivateAssets>
+    </ProjectReference>
+    <ProjectReference Update="..\Ignored\Ignored.csproj" />
+    <PackageReference Include="xunit" Version="2.5.0" />
+  </ItemGroup>
+</Project>
+"#;
+
+        let project_file = ProjectFile::parse(input).unwrap();
+        let references = project_file.items("ProjectReference").collect::<Vec<_>>();
+
+        assert_eq!(references.len(), 1);
+        let (item_group, reference) = references[0];
+        assert_eq!(
+            item_group.condition.as_deref(),
+            Some("'$(Configuration)' == 'Debug'")
+        );
+        assert_eq!(
+            reference.condition.as_deref(),
+            Some("'$(TargetFramework)' == 'net7.0'")
+        );
+        assert_eq!(
+            reference.includes(&Properties::default()),
+            vec![r"..\Shared\Shared.csproj", r"..\Common\Common.csproj"]
+        );
+        assert_eq!(reference.metadata("privateAssets"), Some("all"));
+    }
+}
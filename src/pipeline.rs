@@ -1,60 +1,282 @@
 use std::{
-    collections::HashSet,
+    collections::{BTreeMap, HashMap, HashSet},
     path::{Path, PathBuf},
 };
 
 use console::style;
-use nom::{bytes::complete::take_until, IResult};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use serde_yaml::Value;
 
-use crate::{fenced, project::Project};
+use crate::{
+    config::{Config, PipelineSettings},
+    definition::{is_project_file, scalar, Definition, Job, Step, Variables},
+    error,
+    filter::PathFilter,
+    graph::DependencyGraph,
+    project::Project,
+    properties::BuildContext,
+    repo,
+    solution::{self, is_solution_file},
+    template, trigger,
+    variables::Scope,
+    Error, Result,
+};
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct Pipeline {
+    /// Absolute path of the repository the pipeline belongs to.
+    pub repo_root: PathBuf,
+    /// The configuration and platform the pipeline's projects are evaluated for.
+    pub context: BuildContext,
+    /// Path of the pipeline file, relative to the repository root.
     pub path: PathBuf,
     pub name: String,
+    /// Path of the file the path filter is written to, relative to the repository root.
+    pub filter_file: PathBuf,
     pub projects: Vec<Project>,
+    /// The projects referenced by `projects`, directly or transitively.
+    pub dependencies: Vec<Project>,
+    /// For every project in `dependencies`, the shortest chain of references that leads to it
+    /// from one of `projects`.
+    pub reference_chains: BTreeMap<PathBuf, Vec<PathBuf>>,
+    /// Template files included by the pipeline, relative to the repository root.
+    pub templates: Vec<PathBuf>,
+    /// Solution files the pipeline builds, relative to the repository root. Their projects are
+    /// part of `projects`.
+    pub solutions: Vec<PathBuf>,
+    /// The environments the pipeline's deployment jobs deploy to.
+    pub environments: Vec<String>,
+    /// What `rami-deps.toml` adds to or removes from the pipeline.
+    pub settings: PipelineSettings,
+    /// Templates, projects and references that could not be read. The path filter covers
+    /// everything else. Not serialized, since errors cannot be read back.
+    #[serde(skip)]
+    pub problems: Vec<Error>,
 }
 
 impl Pipeline {
-    pub fn new(path: PathBuf) -> Self {
-        let name = path
-            .parent()
-            .unwrap()
-            .file_name()
-            .unwrap()
-            .to_str()
-            .unwrap()
-            .to_string();
-        let projects = extract_projects(&path);
+    /// Reads the pipeline at `path` and adds its projects to `graph`, applying the settings
+    /// `config` has for it. Fails if the pipeline itself cannot be read, problems with the
+    /// files it leads to are collected in `problems` instead.
+    pub fn new(graph: &mut DependencyGraph, path: PathBuf, config: &Config) -> Result<Self> {
+        Self::load_all(graph, &[path], config).remove(0)
+    }
 
-        Self {
-            name,
-            path,
-            projects,
-        }
+    /// Like [`Pipeline::new`] for several pipelines. The pipeline files and then all of their
+    /// projects are parsed in parallel. Returns the results in the order of `paths`.
+    pub fn load_all(
+        graph: &mut DependencyGraph,
+        paths: &[PathBuf],
+        config: &Config,
+    ) -> Vec<Result<Self>> {
+        let repo_root = graph.repo_root().to_path_buf();
+        let context = graph.context().clone();
+        let mut files = paths
+            .par_iter()
+            .map(|path| PipelineFile::read(&repo_root, &context, path))
+            .collect::<Vec<_>>();
+        tell_apart(&mut files);
+
+        let project_paths = files
+            .iter()
+            .flatten()
+            .flat_map(|file| file.project_paths.iter().cloned())
+            .collect::<Vec<_>>();
+        graph.add_all(&project_paths);
+
+        let graph = &*graph;
+        files
+            .into_par_iter()
+            .map(|file| file.map(|file| file.into_pipeline(graph, config)))
+            .collect()
+    }
+
+    /// The projects the pipeline builds and the projects they reference.
+    fn closure(&self) -> impl Iterator<Item = &Project> + Clone {
+        self.projects.iter().chain(&self.dependencies)
+    }
+
+    /// Package versions and sources only matter when some project uses a package.
+    fn uses_packages(&self) -> bool {
+        self.closure().any(|project| !project.packages.is_empty())
     }
 
     pub fn complete_path_filter(&self) -> String {
         let mut dependencies = HashSet::new();
-        for project in &self.projects {
+        let uses_packages = self.uses_packages();
+        for project in self.closure() {
             dependencies.insert(project.azure_path_filter());
-
-            let related_projects = deep_search_for_projects(project);
-            for project in related_projects {
-                dependencies.insert(project.azure_path_filter());
+            for import in &project.imports {
+                dependencies.insert(repo::azure_path(import) + ";");
+            }
+            if uses_packages {
+                for file in &project.package_files {
+                    dependencies.insert(repo::azure_path(file) + ";");
+                }
             }
+        }
+
+        for include in &self.settings.include {
+            dependencies.insert(format!("{};", include));
+        }
 
-            // Special cases for CustomerPortal due to the mean frontend!
-            if self.name == "CustomerPortal" {
-                dependencies.insert("!/CustomerPortal/apis/modules/*;".to_string());
+        // Changes to the pipeline itself should run it, like changes to its templates. A
+        // pipeline without projects or configured includes keeps an empty filter instead, so
+        // that it is still triggered by every change.
+        if !dependencies.is_empty() {
+            for file in std::iter::once(&self.path)
+                .chain(&self.templates)
+                .chain(&self.solutions)
+            {
+                dependencies.insert(repo::azure_path(file) + ";");
             }
         }
+        for exclude in &self.settings.exclude {
+            dependencies.insert(format!("!{};", exclude));
+        }
 
         let mut dependencies = dependencies.into_iter().collect::<Vec<_>>();
         dependencies.sort();
         dependencies.into_iter().collect::<Vec<_>>().join(" ")
     }
 
+    pub fn path_filter(&self) -> PathFilter {
+        PathFilter::parse(&self.complete_path_filter())
+    }
+
+    /// Compares the path filter with the one in `filter_file`. Returns a unified diff with one
+    /// entry per line if the file is out of date or missing, `None` if it is up to date.
+    pub fn path_filter_diff(&self) -> Result<Option<String>> {
+        let on_disk = match std::fs::read_to_string(self.repo_root.join(&self.filter_file)) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(Error::io(&self.filter_file, e)),
+        };
+        let computed = self.complete_path_filter();
+        if on_disk.trim() == computed.trim() {
+            return Ok(None);
+        }
+
+        // One entry per line, so the diff shows which entries changed.
+        let (old, new) = (filter_lines(&on_disk), filter_lines(&computed));
+        let name = repo::azure_path(&self.filter_file);
+        Ok(Some(
+            similar::TextDiff::from_lines(&old, &new)
+                .unified_diff()
+                .header(&name, &name)
+                .to_string(),
+        ))
+    }
+
+    /// Writes the path filter into the triggers of the pipeline file, see
+    /// [`trigger::apply_path_filter`], and returns whether the file changed. A pipeline with an
+    /// empty path filter keeps its triggers, an empty include list would trigger it on every
+    /// change.
+    pub fn apply_path_filter(&self) -> Result<bool> {
+        let filter = self.path_filter();
+        if filter.include.is_empty() {
+            return Ok(false);
+        }
+
+        let contents = error::read_to_string(&self.repo_root, &self.path)?;
+        let updated = trigger::apply_path_filter(&self.path, &contents, &filter)?;
+        if updated == contents {
+            return Ok(false);
+        }
+        std::fs::write(self.repo_root.join(&self.path), updated)
+            .map_err(|e| Error::io(&self.path, e))?;
+
+        Ok(true)
+    }
+
+    /// Whether a change to `path` (relative to the repository root) affects the pipeline
+    /// according to the dependency graph: the pipeline file, its templates and solutions, anything in the
+    /// directory of a project it builds or references, and the files those projects import.
+    /// Paths included or excluded in the configuration count as such.
+    pub fn depends_on(&self, path: &Path) -> bool {
+        let path = repo::normalize(path);
+        let uses_packages = self.uses_packages();
+        let (included, excluded) = self.configured(&path);
+        if excluded {
+            return false;
+        }
+
+        included
+            || path == self.path
+            || self.templates.contains(&path)
+            || self.solutions.contains(&path)
+            || self.closure().any(|project| {
+                path.starts_with(project.path.parent().unwrap_or(Path::new("")))
+                    || project.imports.contains(&path)
+                    || (uses_packages && project.package_files.contains(&path))
+            })
+    }
+
+    /// Whether `path` is included and whether it is excluded by the configuration.
+    fn configured(&self, path: &Path) -> (bool, bool) {
+        let path = path.to_string_lossy();
+        let filter = |patterns: &[String]| PathFilter {
+            include: patterns.to_vec(),
+            exclude: Vec::new(),
+        };
+
+        (
+            filter(&self.settings.include).matches(&path),
+            filter(&self.settings.exclude).matches(&path),
+        )
+    }
+
+    /// The shortest chain of references from one of `projects` to the project at `path`,
+    /// starting with the entry project and ending with `path`.
+    pub fn reference_chain(&self, path: &Path) -> Option<Vec<PathBuf>> {
+        if self.projects.iter().any(|project| project.path == path) {
+            return Some(vec![path.to_path_buf()]);
+        }
+        self.reference_chains.get(path).cloned()
+    }
+
+    /// Why the pipeline depends on `path`, following the same rules as
+    /// [`Pipeline::depends_on`]. When several projects lead to `path`, the one closest to the
+    /// pipeline's projects is used. `None` if the pipeline does not depend on it.
+    pub fn explain(&self, path: &Path) -> Option<Reason> {
+        let path = repo::normalize(path);
+        match self.configured(&path) {
+            (_, true) => return None,
+            (true, false) => return Some(Reason::Config),
+            (false, false) => {}
+        }
+        if path == self.path {
+            return Some(Reason::PipelineFile);
+        }
+        if self.templates.contains(&path) {
+            return Some(Reason::Template);
+        }
+        if self.solutions.contains(&path) {
+            return Some(Reason::Solution);
+        }
+
+        // The project whose directory is the innermost one containing the path.
+        let owner = self
+            .closure()
+            .map(|project| (project, project.path.parent().unwrap_or(Path::new(""))))
+            .filter(|(_, directory)| path.starts_with(directory))
+            .max_by_key(|(_, directory)| directory.components().count());
+        if let Some((project, _)) = owner {
+            return self.reference_chain(&project.path).map(Reason::Project);
+        }
+
+        let uses_packages = self.uses_packages();
+        self.closure()
+            .filter(|project| {
+                project.imports.contains(&path)
+                    || (uses_packages && project.package_files.contains(&path))
+            })
+            .filter_map(|project| self.reference_chain(&project.path))
+            .min_by_key(Vec::len)
+            .map(Reason::Import)
+    }
+
     pub fn pretty_print(&self) {
         println!(
             "Pipeline {}, {} projects:",
@@ -74,94 +296,486 @@ impl Pipeline {
     }
 }
 
-fn extract_projects(path: &Path) -> Vec<Project> {
-    let pipeline_contents = std::fs::read_to_string(path).unwrap();
+/// Why a pipeline depends on a path, see [`Pipeline::explain`].
+#[derive(Debug, PartialEq)]
+pub enum Reason {
+    /// The path is the pipeline file itself.
+    PipelineFile,
+    /// The path is a template the pipeline includes.
+    Template,
+    /// The path is a solution the pipeline builds.
+    Solution,
+    /// The path is included by `rami-deps.toml`.
+    Config,
+    /// The path is in the directory of the last project of the reference chain.
+    Project(Vec<PathBuf>),
+    /// The last project of the reference chain imports the path, or picks up packages from it.
+    Import(Vec<PathBuf>),
+}
 
-    let mut base_path = PathBuf::new();
-    let parts = path.components().collect::<Vec<_>>();
-    for part in parts {
-        if part.as_os_str() == "SE-CustomerPortal" {
-            base_path.push(part.as_os_str());
-            break;
-        }
+fn filter_lines(path_filter: &str) -> String {
+    path_filter
+        .split_inclusive(';')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| format!("{}\n", entry))
+        .collect()
+}
 
-        base_path.push(part.as_os_str());
+/// The file name of the pipeline file at `path` without the extension and without
+/// `.azure-pipelines`, e.g. `build` for both `build.yml` and `build.azure-pipelines.yml`.
+fn base_name(path: &Path) -> Result<&str> {
+    let stem = path
+        .file_stem()
+        .map_or(Ok(""), |stem| repo::to_str(Path::new(stem)))?;
+
+    Ok(stem.strip_suffix(".azure-pipelines").unwrap_or(stem))
+}
+
+/// The name of the pipeline file at `path`: `build` for `build.yml` or
+/// `build.azure-pipelines.yml`, and the directory name for a plain `azure-pipelines.yml`.
+fn pipeline_name(repo_root: &Path, path: &Path) -> Result<String> {
+    let stem = base_name(path)?;
+    if stem == "azure-pipelines" {
+        return Ok(repo_root
+            .join(path)
+            .parent()
+            .and_then(Path::file_name)
+            .map(|name| repo::to_str(Path::new(name)).map(str::to_string))
+            .transpose()?
+            .unwrap_or_default());
     }
 
-    let mut projects = Vec::new();
-    for line in pipeline_contents.lines() {
-        // We don't care about the tests!
-        if line.contains("Tests.csproj") || line.contains("Test.csproj") || line.contains(".Test") {
-            continue;
+    Ok(stem.to_string())
+}
+
+/// `.azure-pathfilter` next to an `azure-pipelines.yml`, otherwise named after the pipeline
+/// file, e.g. `build.azure-pathfilter` for `build.yml`.
+fn filter_file(path: &Path) -> Result<PathBuf> {
+    Ok(match base_name(path)? {
+        "azure-pipelines" => path.with_file_name(".azure-pathfilter"),
+        stem => path.with_file_name(format!("{}.azure-pathfilter", stem)),
+    })
+}
+
+/// Pipeline files in the same directory can lead to the same name or path filter file, e.g.
+/// `azure-pipelines.yml` and `azure-pipelines.yaml`. Those pipelines are named by their path
+/// instead, and their path filter file by their whole file name.
+fn tell_apart(files: &mut [Result<PipelineFile>]) {
+    let mut names = HashMap::<_, usize>::new();
+    let mut filter_files = HashMap::<_, usize>::new();
+    for file in files.iter().flatten() {
+        *names
+            .entry((file.path.parent().map(Path::to_path_buf), file.name.clone()))
+            .or_default() += 1;
+        *filter_files.entry(file.filter_file.clone()).or_default() += 1;
+    }
+
+    for file in files.iter_mut().flatten() {
+        if names[&(file.path.parent().map(Path::to_path_buf), file.name.clone())] > 1 {
+            file.name = file.path.to_string_lossy().replace('\\', "/");
+        }
+        if filter_files[&file.filter_file] > 1 {
+            let file_name = file.path.file_name().unwrap_or_default().to_string_lossy();
+            file.filter_file = file
+                .path
+                .with_file_name(format!("{}.azure-pathfilter", file_name));
         }
+    }
+}
+
+/// The names shared by several of `pipelines`, with the paths of the pipelines using each.
+/// Pipelines are looked up by name, so these have to be named apart in the configuration.
+pub fn duplicate_names(pipelines: &[Result<Pipeline>]) -> BTreeMap<&str, Vec<&Path>> {
+    let mut paths = BTreeMap::<&str, Vec<&Path>>::new();
+    for pipeline in pipelines.iter().flatten() {
+        paths
+            .entry(&pipeline.name)
+            .or_default()
+            .push(&pipeline.path);
+    }
+    paths.retain(|_, paths| paths.len() > 1);
+
+    paths
+}
 
-        if let Ok((_, project_path)) = extract_project_path(line, "csproj", "\"") {
-            let combined_path = base_path.join(project_path);
-            projects.push(Project::new(combined_path));
-        } else if let Ok((_, project_path)) = extract_project_path(line, "csproj", "'") {
-            let combined_path = base_path.join(project_path);
-            projects.push(Project::new(combined_path));
+/// A pipeline file that has been read, before its projects are looked up in the graph.
+struct PipelineFile {
+    path: PathBuf,
+    name: String,
+    filter_file: PathBuf,
+    /// The existing project files the pipeline builds, directly or through a solution.
+    project_paths: Vec<PathBuf>,
+    templates: Vec<PathBuf>,
+    solutions: Vec<PathBuf>,
+    environments: Vec<String>,
+    problems: Vec<Error>,
+}
+
+impl PipelineFile {
+    fn read(repo_root: &Path, context: &BuildContext, path: &Path) -> Result<Self> {
+        let path = repo::relative_to(repo_root, path);
+        let name = pipeline_name(repo_root, &path)?;
+        let filter_file = filter_file(&path)?;
+
+        let mut problems = Vec::new();
+        let (mut project_paths, templates, environments) =
+            extract_projects(repo_root, &path, &mut problems)?;
+        project_paths.retain(|project_path| {
+            let exists = repo_root.join(project_path).is_file();
+            if !exists {
+                problems.push(Error::MissingReference {
+                    project: path.clone(),
+                    reference: project_path.clone(),
+                });
+            }
+            exists
+        });
+
+        // A solution stands for the projects it builds in the pipeline's configuration.
+        let (solutions, mut project_paths): (Vec<_>, Vec<_>) = project_paths
+            .into_iter()
+            .partition(|project_path| is_solution_file(&project_path.to_string_lossy()));
+        for solution in &solutions {
+            match solution::read_projects(repo_root, solution, context) {
+                Ok(projects) => {
+                    for project_path in projects {
+                        if !repo_root.join(&project_path).is_file() {
+                            problems.push(Error::MissingReference {
+                                project: solution.clone(),
+                                reference: project_path,
+                            });
+                        } else if !project_paths.contains(&project_path) {
+                            project_paths.push(project_path);
+                        }
+                    }
+                }
+                Err(e) => problems.push(e),
+            }
         }
+
+        Ok(Self {
+            path,
+            name,
+            filter_file,
+            project_paths,
+            templates,
+            solutions,
+            environments,
+            problems,
+        })
     }
 
-    projects
+    fn into_pipeline(mut self, graph: &DependencyGraph, config: &Config) -> Pipeline {
+        if let Some(name) = config.pipeline_name(&self.path) {
+            self.name = name.to_string();
+        }
+        let settings = config.pipeline(&self.name, &self.path);
+        // Ignored projects and, unless configured otherwise, test projects are left out.
+        let left_out = |path: &Path| {
+            settings.ignores(path)
+                || (!config.tests.include && graph.project(path).is_some_and(|p| p.is_test))
+        };
+        let mut entries = Vec::new();
+        for project_path in &self.project_paths {
+            let key = graph.canonical(project_path);
+            if !entries.contains(&key) && !left_out(&key) {
+                entries.push(key);
+            }
+        }
+
+        let closure = graph.closure(&entries);
+        self.problems.extend(closure.problems);
+        let projects = entries
+            .iter()
+            .filter_map(|path| graph.project(path).cloned())
+            .collect();
+        let dependencies = closure
+            .projects
+            .iter()
+            .filter(|path| !entries.contains(path) && !left_out(path))
+            .filter_map(|path| graph.project(path).cloned())
+            .collect();
+        let mut reference_chains = graph.chains(&entries);
+        reference_chains.retain(|path, _| !entries.contains(path) && !left_out(path));
+
+        Pipeline {
+            repo_root: graph.repo_root().to_path_buf(),
+            context: graph.context().clone(),
+            name: self.name,
+            filter_file: self.filter_file,
+            path: self.path,
+            projects,
+            dependencies,
+            reference_chains,
+            templates: self.templates,
+            solutions: self.solutions,
+            environments: self.environments,
+            settings,
+            problems: self.problems,
+        }
+    }
 }
 
-fn extract_project_path<'a>(
-    input: &'a str,
-    project_extension: &str,
-    fence_char: &'a str,
-) -> IResult<&'a str, String> {
-    let (input, _) = take_until(fence_char)(input)?;
+/// Finds the project and solution files built by the pipeline at `path`, together with the templates it
+/// includes and the environments it deploys to.
+fn extract_projects(
+    repo_root: &Path,
+    path: &Path,
+    problems: &mut Vec<Error>,
+) -> Result<(Vec<PathBuf>, Vec<PathBuf>, Vec<String>)> {
+    let pipeline_contents = error::read_to_string(repo_root, path)?;
+    let definition =
+        template::parse_definition(&pipeline_contents, None).map_err(|e| Error::yaml(path, e))?;
 
-    let (input, path) = fenced(fence_char, fence_char)(input)?;
+    let mut walker = DefinitionWalker::new(repo_root);
+    walker.walk(path, &definition, &Scope::default());
+    problems.append(&mut walker.problems);
 
-    match PathBuf::from(path).extension() {
-        Some(extension) if extension == project_extension => Ok((input, path.to_string())),
-        _ => Err(nom::Err::Error(nom::error::Error::new(
-            input,
-            nom::error::ErrorKind::Tag,
-        ))),
+    let excluded = walker
+        .patterns
+        .iter()
+        .filter_map(|pattern| pattern.strip_prefix('!'))
+        .filter_map(|pattern| glob::Pattern::new(pattern).ok())
+        .collect::<Vec<_>>();
+
+    let mut project_paths = Vec::new();
+    for pattern in walker
+        .patterns
+        .iter()
+        .filter(|pattern| !pattern.starts_with('!'))
+    {
+        for project_path in resolve_pattern(repo_root, pattern) {
+            if excluded
+                .iter()
+                .any(|exclude| exclude.matches_path(&project_path))
+            {
+                continue;
+            }
+
+            if !project_paths.contains(&project_path) {
+                project_paths.push(project_path);
+            }
+        }
     }
+
+    Ok((project_paths, walker.templates, walker.environments))
 }
 
-fn deep_search_for_projects(project: &Project) -> Vec<Project> {
-    if project.references.is_empty() {
-        return Vec::new();
+/// Templates may include other templates, Azure Pipelines stops at the same depth.
+const MAX_TEMPLATE_DEPTH: usize = 20;
+
+/// Walks a pipeline definition and every template it includes, collecting the project patterns
+/// of each step with the variables of its scope expanded.
+struct DefinitionWalker<'a> {
+    repo_root: &'a Path,
+    /// Project patterns in the order they appear, without duplicates.
+    patterns: Vec<String>,
+    /// Included template files, relative to the repository root.
+    templates: Vec<PathBuf>,
+    /// The environments deployment jobs deploy to, without duplicates.
+    environments: Vec<String>,
+    problems: Vec<Error>,
+    depth: usize,
+}
+
+impl<'a> DefinitionWalker<'a> {
+    fn new(repo_root: &'a Path) -> Self {
+        Self {
+            repo_root,
+            patterns: Vec::new(),
+            templates: Vec::new(),
+            environments: Vec::new(),
+            problems: Vec::new(),
+            depth: 0,
+        }
     }
 
-    let mut projects = Vec::new();
+    fn walk(&mut self, file: &Path, definition: &Definition, scope: &Scope) {
+        let scope = self.variables_scope(file, scope, definition.variables.as_ref());
 
-    for reference in &project.references {
-        let mut path = project.path.to_path_buf();
-        path.pop(); // pop from file to directory
-        path.push(&reference.include_path);
+        self.walk_steps(file, definition.steps.iter().collect(), &scope);
+        self.walk_jobs(file, &definition.jobs, &scope);
+        for stage in &definition.stages {
+            match &stage.template {
+                Some(template) => {
+                    self.walk_template(file, template, stage.parameters.as_ref(), &scope)
+                }
+                None => {
+                    let stage_scope = self.variables_scope(file, &scope, stage.variables.as_ref());
+                    self.walk_jobs(file, &stage.jobs, &stage_scope);
+                }
+            }
+        }
 
-        let project = Project::new(path);
-        projects.push(project.clone());
-        if reference
-            .include_path
-            .to_str()
-            .unwrap()
-            .contains("Notification")
-        {
-            println!("Found Notification project: {:?}", project.path);
+        if let Some(extends) = &definition.extends {
+            self.walk_template(file, &extends.template, extends.parameters.as_ref(), &scope);
+        }
+    }
+
+    fn walk_jobs(&mut self, file: &Path, jobs: &[Job], scope: &Scope) {
+        for job in jobs {
+            match &job.template {
+                Some(template) => {
+                    self.walk_template(file, template, job.parameters.as_ref(), scope)
+                }
+                None => {
+                    let job_scope = self.variables_scope(file, scope, job.variables.as_ref());
+                    if let Some(environment) = &job.environment {
+                        let environment = job_scope.expand(&environment.target());
+                        if !self.environments.contains(&environment) {
+                            self.environments.push(environment);
+                        }
+                    }
+                    self.walk_steps(file, job.all_steps(), &job_scope);
+                }
+            }
         }
-        let related_projects = deep_search_for_projects(&project);
-        projects.extend(related_projects);
     }
 
-    projects
+    fn walk_steps(&mut self, file: &Path, steps: Vec<&Step>, scope: &Scope) {
+        for step in steps {
+            if let Some(template) = &step.template {
+                self.walk_template(file, template, step.parameters.as_ref(), scope);
+                continue;
+            }
+
+            for pattern in step.project_patterns() {
+                let pattern = scope.expand(&pattern).replace('\\', "/");
+                if pattern.contains("$(") || pattern.contains("${{") || pattern.contains("$[") {
+                    self.problems.push(Error::UnresolvedVariable {
+                        file: file.to_path_buf(),
+                        expression: pattern,
+                    });
+                    continue;
+                }
+                if !self.patterns.contains(&pattern) {
+                    self.patterns.push(pattern);
+                }
+            }
+        }
+    }
+
+    fn walk_template(
+        &mut self,
+        file: &Path,
+        reference: &str,
+        arguments: Option<&Value>,
+        scope: &Scope,
+    ) {
+        if self.depth >= MAX_TEMPLATE_DEPTH {
+            return;
+        }
+        let template = match template::load(self.repo_root, file, reference, arguments, scope) {
+            Ok(Some(template)) => template,
+            Ok(None) => return,
+            Err(e) => {
+                self.problems.push(e);
+                return;
+            }
+        };
+
+        if !self.templates.contains(&template.path) {
+            self.templates.push(template.path.clone());
+        }
+
+        self.depth += 1;
+        self.walk(&template.path, &template.definition, scope);
+        self.depth -= 1;
+    }
+
+    /// Creates the scope for a level of the pipeline. Variables are applied in order, so that a
+    /// variable template can be overridden by the variables following it.
+    fn variables_scope(
+        &mut self,
+        file: &Path,
+        scope: &Scope,
+        variables: Option<&Variables>,
+    ) -> Scope {
+        let Some(Variables::List(variables)) = variables else {
+            return scope.with_variables(variables);
+        };
+
+        let mut scope = scope.clone();
+        for variable in variables {
+            if let Some(reference) = &variable.template {
+                if self.depth >= MAX_TEMPLATE_DEPTH {
+                    continue;
+                }
+                let template = match template::load(
+                    self.repo_root,
+                    file,
+                    reference,
+                    variable.parameters.as_ref(),
+                    &scope,
+                ) {
+                    Ok(Some(template)) => template,
+                    Ok(None) => continue,
+                    Err(e) => {
+                        self.problems.push(e);
+                        continue;
+                    }
+                };
+
+                if !self.templates.contains(&template.path) {
+                    self.templates.push(template.path.clone());
+                }
+                self.depth += 1;
+                scope = self.variables_scope(
+                    &template.path,
+                    &scope,
+                    template.definition.variables.as_ref(),
+                );
+                self.depth -= 1;
+            } else if let (Some(name), Some(value)) =
+                (&variable.name, variable.value.as_ref().and_then(scalar))
+            {
+                scope.set_variable(name, value);
+            }
+        }
+
+        scope
+    }
+}
+
+/// Resolves a (possibly globbed) project pattern to the project files it matches, relative to
+/// the repository root.
+fn resolve_pattern(repo_root: &Path, pattern: &str) -> Vec<PathBuf> {
+    if !pattern.contains(['*', '?', '[']) {
+        return if is_project_file(pattern) || is_solution_file(pattern) {
+            vec![repo::normalize(Path::new(pattern))]
+        } else {
+            Vec::new()
+        };
+    }
+
+    let root = glob::Pattern::escape(&repo_root.to_string_lossy());
+    let Ok(paths) = glob::glob(&format!("{}/{}", root, pattern.trim_start_matches('/'))) else {
+        return Vec::new();
+    };
+
+    let mut project_paths = paths
+        .filter_map(Result::ok)
+        .filter(|path| {
+            let path = path.to_str().unwrap_or_default();
+            is_project_file(path) || is_solution_file(path)
+        })
+        .map(|path| repo::relative_to(repo_root, &path))
+        .collect::<Vec<_>>();
+    project_paths.sort();
+
+    project_paths
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::{config::DiscoverySettings, discovery, testing::write_tree};
 
     #[test]
     fn test_extract_includes() {
         let input = r#"
-        trigger:
+trigger:
   branches:
     include:
       - main
@@ -310,12 +924,14 @@ stages:
 
         "#;
 
-        let mut projects = Vec::new();
-        for line in input.lines() {
-            if let Ok((_, include)) = extract_project_path(line, "csproj", "\"") {
-                projects.push(include);
-            }
-        }
+        let definition = template::parse_definition(input, None).unwrap();
+        let mut walker = DefinitionWalker::new(Path::new("."));
+        walker.walk(
+            Path::new("CustomerPortal/apis/modules/RentalModule/azure-pipelines.yml"),
+            &definition,
+            &Scope::default(),
+        );
+        let projects = walker.patterns;
 
         assert_eq!(
             projects,
@@ -325,4 +941,619 @@ stages:
             ]
         );
     }
+
+    #[test]
+    fn test_explain() {
+        let dir = tempfile::tempdir().unwrap();
+        let reference = |name: &str| {
+            format!(
+                r#"<Project><ItemGroup><ProjectReference Include="..\{0}\{0}.csproj" /></ItemGroup></Project>"#,
+                name
+            )
+        };
+        write_tree(
+            dir.path(),
+            &[
+                ("Api/Api.csproj", &reference("Domain")),
+                ("Domain/Domain.csproj", &reference("Core")),
+                ("Core/Core.csproj", "<Project />"),
+                ("Directory.Build.props", "<Project />"),
+                (
+                    "Rental/azure-pipelines.yml",
+                    "steps:\n  - task: DotNetCoreCLI@2\n    inputs:\n      projects: Api/Api.csproj\n",
+                ),
+            ],
+        );
+
+        let mut graph = DependencyGraph::new(dir.path(), &BuildContext::default());
+        let pipeline = Pipeline::new(
+            &mut graph,
+            PathBuf::from("Rental/azure-pipelines.yml"),
+            &Config::default(),
+        )
+        .unwrap();
+
+        let chain = |paths: &[&str]| paths.iter().map(PathBuf::from).collect::<Vec<_>>();
+        assert_eq!(
+            pipeline.explain(Path::new("Core/Services/Clock.cs")),
+            Some(Reason::Project(chain(&[
+                "Api/Api.csproj",
+                "Domain/Domain.csproj",
+                "Core/Core.csproj"
+            ])))
+        );
+        assert_eq!(
+            pipeline.explain(Path::new("Directory.Build.props")),
+            Some(Reason::Import(chain(&["Api/Api.csproj"])))
+        );
+        assert_eq!(
+            pipeline.explain(Path::new("Rental/azure-pipelines.yml")),
+            Some(Reason::PipelineFile)
+        );
+        assert_eq!(pipeline.explain(Path::new("Other/Other.cs")), None);
+    }
+
+    #[test]
+    fn test_pipeline_names() {
+        let dir = tempfile::tempdir().unwrap();
+        let steps = "steps:\n  - script: npm ci\n";
+        write_tree(
+            dir.path(),
+            &[
+                ("Rental/azure-pipelines.yml", steps),
+                ("Rental/release.azure-pipelines.yaml", steps),
+                ("Web/azure-pipelines.yml", steps),
+                ("Web/azure-pipelines.yaml", steps),
+                ("ci/build.yml", steps),
+                ("cd/build.yml", steps),
+            ],
+        );
+        let load = |paths: &[&str], config: &Config| {
+            let mut graph = DependencyGraph::new(dir.path(), &BuildContext::default());
+            let paths = paths.iter().map(PathBuf::from).collect::<Vec<_>>();
+            Pipeline::load_all(&mut graph, &paths, config)
+        };
+        let names = |pipelines: &[Result<Pipeline>]| {
+            pipelines
+                .iter()
+                .flatten()
+                .map(|pipeline| (pipeline.name.clone(), pipeline.filter_file.clone()))
+                .collect::<Vec<_>>()
+        };
+
+        let pipelines = load(
+            &[
+                "Rental/azure-pipelines.yml",
+                "Rental/release.azure-pipelines.yaml",
+            ],
+            &Config::default(),
+        );
+        assert_eq!(
+            names(&pipelines),
+            vec![
+                (
+                    "Rental".to_string(),
+                    PathBuf::from("Rental/.azure-pathfilter")
+                ),
+                (
+                    "release".to_string(),
+                    PathBuf::from("Rental/release.azure-pathfilter")
+                ),
+            ]
+        );
+
+        // Pipeline files in one directory sharing the name and filter file are told apart.
+        let pipelines = load(
+            &["Web/azure-pipelines.yml", "Web/azure-pipelines.yaml"],
+            &Config::default(),
+        );
+        assert_eq!(
+            names(&pipelines),
+            vec![
+                (
+                    "Web/azure-pipelines.yml".to_string(),
+                    PathBuf::from("Web/azure-pipelines.yml.azure-pathfilter")
+                ),
+                (
+                    "Web/azure-pipelines.yaml".to_string(),
+                    PathBuf::from("Web/azure-pipelines.yaml.azure-pathfilter")
+                ),
+            ]
+        );
+        assert!(duplicate_names(&pipelines).is_empty());
+
+        // In different directories they are not, the configuration has to name them.
+        let paths = ["ci/build.yml", "cd/build.yml"];
+        let pipelines = load(&paths, &Config::default());
+        assert_eq!(
+            duplicate_names(&pipelines),
+            BTreeMap::from([(
+                "build",
+                vec![Path::new("ci/build.yml"), Path::new("cd/build.yml")]
+            )])
+        );
+        let config =
+            Config::parse("[[pipeline]]\npath = \"cd/build.yml\"\nname = \"deploy\"\n").unwrap();
+        let pipelines = load(&paths, &config);
+        assert!(duplicate_names(&pipelines).is_empty());
+        assert_eq!(pipelines[1].as_ref().unwrap().name, "deploy");
+    }
+
+    #[test]
+    fn test_path_filter() {
+        let dir = tempfile::tempdir().unwrap();
+        write_tree(
+            dir.path(),
+            &[
+                ("Api/Api.csproj", "<Project />"),
+                (
+                    "pipelines/build.yml",
+                    "steps:\n  - script: dotnet build Api/Api.csproj\n",
+                ),
+                (
+                    "Rental/azure-pipelines.yml",
+                    "steps:\n  - template: /pipelines/build.yml\n",
+                ),
+                ("pipelines/npm.yml", "steps:\n  - script: npm ci\n"),
+                (
+                    "Frontend/azure-pipelines.yml",
+                    "steps:\n  - template: /pipelines/npm.yml\n",
+                ),
+            ],
+        );
+
+        let mut graph = DependencyGraph::new(dir.path(), &BuildContext::default());
+        let paths = ["Rental/azure-pipelines.yml", "Frontend/azure-pipelines.yml"];
+        let pipelines =
+            Pipeline::load_all(&mut graph, &paths.map(PathBuf::from), &Config::default());
+        let pipelines = pipelines.iter().flatten().collect::<Vec<_>>();
+
+        assert_eq!(
+            pipelines[0].complete_path_filter(),
+            "/Api/*; /Rental/azure-pipelines.yml; /pipelines/build.yml;"
+        );
+        // Without projects, the pipeline's own files would be its whole filter.
+        assert_eq!(pipelines[1].complete_path_filter(), "");
+    }
+
+    #[test]
+    fn test_path_filter_diff() {
+        let dir = tempfile::tempdir().unwrap();
+        write_tree(
+            dir.path(),
+            &[
+                ("Api/Api.csproj", "<Project />"),
+                (
+                    "Rental/azure-pipelines.yml",
+                    "steps:\n  - script: dotnet build Api/Api.csproj\n",
+                ),
+            ],
+        );
+
+        let mut graph = DependencyGraph::new(dir.path(), &BuildContext::default());
+        let path = PathBuf::from("Rental/azure-pipelines.yml");
+        let pipeline = Pipeline::new(&mut graph, path, &Config::default()).unwrap();
+        let file = dir.path().join("Rental/.azure-pathfilter");
+
+        // A missing file is compared as an empty one.
+        assert_eq!(
+            pipeline.path_filter_diff().unwrap().unwrap(),
+            "--- /Rental/.azure-pathfilter\n+++ /Rental/.azure-pathfilter\n@@ -0,0 +1,2 @@\n+/Api/*;\n+/Rental/azure-pipelines.yml;\n"
+        );
+
+        std::fs::write(&file, "/Api/*; /Old/*;").unwrap();
+        assert_eq!(
+            pipeline.path_filter_diff().unwrap().unwrap(),
+            "--- /Rental/.azure-pathfilter\n+++ /Rental/.azure-pathfilter\n@@ -1,2 +1,2 @@\n /Api/*;\n-/Old/*;\n+/Rental/azure-pipelines.yml;\n"
+        );
+
+        std::fs::write(&file, pipeline.complete_path_filter() + "\n").unwrap();
+        assert_eq!(pipeline.path_filter_diff().unwrap(), None);
+
+        std::fs::write(&file, [0xff, 0xfe]).unwrap();
+        assert!(matches!(
+            pipeline.path_filter_diff(),
+            Err(Error::IoError { path, .. }) if path == Path::new("Rental/.azure-pathfilter")
+        ));
+    }
+
+    #[test]
+    fn test_apply_path_filter() {
+        let dir = tempfile::tempdir().unwrap();
+        let rental = r#"# Builds the rental module
+trigger: [main] # release branches build manually
+
+steps:
+  # the API only, the web app has its own pipeline
+  - script: dotnet build Api/Api.csproj
+"#;
+        let frontend = "trigger:\n  - main\n\nsteps:\n  - script: npm ci\n";
+        write_tree(
+            dir.path(),
+            &[
+                ("Api/Api.csproj", "<Project />"),
+                ("Rental/azure-pipelines.yml", rental),
+                ("Frontend/azure-pipelines.yml", frontend),
+            ],
+        );
+
+        let mut graph = DependencyGraph::new(dir.path(), &BuildContext::default());
+        let paths = ["Rental/azure-pipelines.yml", "Frontend/azure-pipelines.yml"];
+        let pipelines =
+            Pipeline::load_all(&mut graph, &paths.map(PathBuf::from), &Config::default());
+        let pipelines = pipelines.iter().flatten().collect::<Vec<_>>();
+        assert!(pipelines[0].apply_path_filter().unwrap());
+        assert!(!pipelines[1].apply_path_filter().unwrap());
+
+        let read = |path: &str| std::fs::read_to_string(dir.path().join(path)).unwrap();
+        assert_eq!(
+            read("Rental/azure-pipelines.yml"),
+            r#"# Builds the rental module
+trigger: # release branches build manually
+  branches:
+    include:
+      - main
+  paths:
+    include:
+      - Api/*
+      - Rental/azure-pipelines.yml
+
+steps:
+  # the API only, the web app has its own pipeline
+  - script: dotnet build Api/Api.csproj
+"#
+        );
+        // Without projects, the pipeline keeps being triggered by every change.
+        assert_eq!(read("Frontend/azure-pipelines.yml"), frontend);
+        assert!(!pipelines[0].apply_path_filter().unwrap());
+    }
+
+    #[test]
+    fn test_serialize() {
+        let dir = tempfile::tempdir().unwrap();
+        write_tree(
+            dir.path(),
+            &[
+                (
+                    "Api/Api.csproj",
+                    r#"<Project><ItemGroup><ProjectReference Include="..\Core\Core.csproj" /></ItemGroup></Project>"#,
+                ),
+                ("Core/Core.csproj", "<Project />"),
+                (
+                    "azure-pipelines.yml",
+                    "steps:\n  - script: dotnet build Api/Api.csproj\n",
+                ),
+            ],
+        );
+
+        let mut graph = DependencyGraph::new(dir.path(), &BuildContext::default());
+        let path = PathBuf::from("azure-pipelines.yml");
+        let pipeline = Pipeline::new(&mut graph, path, &Config::default()).unwrap();
+
+        // `--format json` prints these, scripts read them back.
+        let json = serde_json::to_value(&pipeline).unwrap();
+        assert_eq!(
+            json.as_object().unwrap().keys().collect::<Vec<_>>(),
+            vec![
+                "context",
+                "dependencies",
+                "environments",
+                "filter_file",
+                "name",
+                "path",
+                "projects",
+                "reference_chains",
+                "repo_root",
+                "settings",
+                "solutions",
+                "templates"
+            ]
+        );
+        assert_eq!(
+            json["reference_chains"],
+            serde_json::json!({ "Core/Core.csproj": ["Api/Api.csproj", "Core/Core.csproj"] })
+        );
+        assert_eq!(json["projects"][0]["path"], "Api/Api.csproj");
+        assert_eq!(
+            json["projects"][0]["references"][0]["include_path"],
+            "Core/Core.csproj"
+        );
+
+        let read_back = serde_json::from_value::<Pipeline>(json.clone()).unwrap();
+        assert_eq!(serde_json::to_value(&read_back).unwrap(), json);
+        assert_eq!(
+            read_back.complete_path_filter(),
+            pipeline.complete_path_filter()
+        );
+
+        let filter = pipeline.path_filter();
+        let json = serde_json::to_string(&filter).unwrap();
+        assert_eq!(
+            json,
+            r#"{"include":["/Api/*","/Core/*","/azure-pipelines.yml"],"exclude":[]}"#
+        );
+        assert_eq!(serde_json::from_str::<PathFilter>(&json).unwrap(), filter);
+    }
+
+    #[test]
+    fn test_environments() {
+        let dir = tempfile::tempdir().unwrap();
+        write_tree(
+            dir.path(),
+            &[
+                (
+                    "pipelines/deploy.yml",
+                    r#"
+parameters:
+  - name: environment
+    type: string
+jobs:
+  - deployment: Deploy
+    environment: ${{ parameters.environment }}
+"#,
+                ),
+                (
+                    "azure-pipelines.yml",
+                    r#"
+variables:
+  stage: uat
+stages:
+  - stage: Build
+    jobs:
+      - job: Build
+        steps:
+          - script: npm ci
+  - stage: Dev
+    jobs:
+      - deployment: DeployToDev
+        environment: dev-customer-portal
+      - deployment: DeployAgain
+        environment: dev-customer-portal
+  - stage: Uat
+    jobs:
+      - deployment: DeployToUat
+        environment: $(stage)-customer-portal
+  - stage: Prod
+    jobs:
+      - deployment: DeployToProd
+        environment:
+          name: prod-customer-portal
+          resourceName: web-01
+      - template: /pipelines/deploy.yml
+        parameters:
+          environment: prod-functions
+"#,
+                ),
+            ],
+        );
+
+        let mut graph = DependencyGraph::new(dir.path(), &BuildContext::default());
+        let path = PathBuf::from("azure-pipelines.yml");
+        let pipeline = Pipeline::new(&mut graph, path, &Config::default()).unwrap();
+
+        assert_eq!(
+            pipeline.environments,
+            vec![
+                "dev-customer-portal",
+                "uat-customer-portal",
+                "prod-customer-portal.web-01",
+                "prod-functions"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_load_all_in_parallel() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut files = vec![(
+            "Shared/Shared.csproj".to_string(),
+            r#"<Project><ItemGroup><ProjectReference Include="..\Missing\Missing.csproj" /></ItemGroup></Project>"#
+                .to_string(),
+        )];
+        for module in 0..20 {
+            let name = format!("Module{}", module);
+            files.push((
+                format!("{0}/{0}.Api/{0}.Api.csproj", name),
+                format!(
+                    r#"<Project><ItemGroup><ProjectReference Include="..\{0}.Domain\{0}.Domain.csproj" /><ProjectReference Include="..\..\Shared\Shared.csproj" /></ItemGroup></Project>"#,
+                    name
+                ),
+            ));
+            files.push((
+                format!("{0}/{0}.Domain/{0}.Domain.csproj", name),
+                r#"<Project><ItemGroup><ProjectReference Include="..\..\Shared\Shared.csproj" /></ItemGroup></Project>"#
+                    .to_string(),
+            ));
+            files.push((
+                format!("{}/azure-pipelines.yml", name),
+                format!(
+                    "steps:\n  - script: dotnet build {0}/{0}.Api/{0}.Api.csproj\n",
+                    name
+                ),
+            ));
+        }
+        let files = files
+            .iter()
+            .map(|(path, contents)| (path.as_str(), contents.as_str()))
+            .collect::<Vec<_>>();
+        write_tree(dir.path(), &files);
+
+        // What `--jobs` changes must not change the output.
+        let load = |threads: usize| {
+            let pool = rayon::ThreadPoolBuilder::new()
+                .num_threads(threads)
+                .build()
+                .unwrap();
+            pool.install(|| {
+                let settings = DiscoverySettings::default();
+                let paths = discovery::find_pipelines(dir.path(), dir.path(), &settings, threads);
+                let mut graph = DependencyGraph::new(dir.path(), &BuildContext::default());
+                Pipeline::load_all(&mut graph, &paths, &Config::default())
+                    .into_iter()
+                    .map(|pipeline| {
+                        let pipeline = pipeline.unwrap();
+                        let problems = pipeline
+                            .problems
+                            .iter()
+                            .map(ToString::to_string)
+                            .collect::<Vec<_>>();
+                        (serde_json::to_value(&pipeline).unwrap(), problems)
+                    })
+                    .collect::<Vec<_>>()
+            })
+        };
+
+        let sequential = load(1);
+        assert_eq!(sequential.len(), 20);
+        assert_eq!(sequential[0].0["name"], "Module0");
+        assert_eq!(sequential[0].1.len(), 1);
+        for _ in 0..3 {
+            assert_eq!(load(8), sequential);
+        }
+    }
+
+    #[test]
+    fn test_test_projects() {
+        let dir = tempfile::tempdir().unwrap();
+        write_tree(
+            dir.path(),
+            &[
+                (
+                    "Api/Api.csproj",
+                    r#"<Project><ItemGroup><ProjectReference Include="..\Contest.Api\Contest.Api.csproj" /></ItemGroup></Project>"#,
+                ),
+                ("Contest.Api/Contest.Api.csproj", "<Project />"),
+                (
+                    "Api.Specs/Api.Specs.csproj",
+                    "<Project><PropertyGroup><IsTestProject>true</IsTestProject></PropertyGroup></Project>",
+                ),
+                (
+                    "Api.Checks/Api.Checks.csproj",
+                    r#"<Project><ItemGroup><PackageReference Include="xunit" Version="2.9.0" /></ItemGroup></Project>"#,
+                ),
+                ("Api.Tests/Api.Tests.csproj", "<Project />"),
+                (
+                    "azure-pipelines.yml",
+                    "steps:\n  - task: DotNetCoreCLI@2\n    inputs:\n      projects: 'Api*/*.csproj'\n",
+                ),
+            ],
+        );
+
+        let load = |config: &Config| {
+            let mut graph = DependencyGraph::new(dir.path(), &BuildContext::default());
+            let pipeline =
+                Pipeline::new(&mut graph, PathBuf::from("azure-pipelines.yml"), config).unwrap();
+            let paths = |projects: &[Project]| {
+                let mut paths = projects
+                    .iter()
+                    .map(|project| (project.path.clone(), project.is_test))
+                    .collect::<Vec<_>>();
+                paths.sort();
+                paths
+            };
+            (paths(&pipeline.projects), paths(&pipeline.dependencies))
+        };
+
+        let (projects, dependencies) = load(&Config::default());
+        assert_eq!(projects, vec![(PathBuf::from("Api/Api.csproj"), false)]);
+        assert_eq!(
+            dependencies,
+            vec![(PathBuf::from("Contest.Api/Contest.Api.csproj"), false)]
+        );
+
+        let mut config = Config::default();
+        config.tests.include = true;
+        let (projects, _) = load(&config);
+        assert_eq!(
+            projects,
+            vec![
+                (PathBuf::from("Api/Api.csproj"), false),
+                (PathBuf::from("Api.Checks/Api.Checks.csproj"), true),
+                (PathBuf::from("Api.Specs/Api.Specs.csproj"), true),
+                (PathBuf::from("Api.Tests/Api.Tests.csproj"), true),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_templates() {
+        let dir = tempfile::tempdir().unwrap();
+        write_tree(
+            dir.path(),
+            &[
+                (
+                    "pipelines/templates/stages.yml",
+                    r#"
+parameters:
+  - name: buildSteps
+    type: stepList
+stages:
+  - stage: Build
+    jobs:
+      - job: Build
+        steps:
+          - ${{ parameters.buildSteps }}
+"#,
+                ),
+                (
+                    "pipelines/templates/build-dotnet.yml",
+                    r#"
+parameters:
+  - name: project
+    type: string
+steps:
+  - task: DotNetCoreCLI@2
+    inputs:
+      projects: ${{ parameters.project }}
+"#,
+                ),
+                (
+                    "pipelines/templates/variables.yml",
+                    "variables:\n  modulePath: CustomerPortal/apis/modules/RentalModule\n",
+                ),
+                (
+                    "CustomerPortal/apis/modules/RentalModule/azure-pipelines.yml",
+                    r#"
+variables:
+  - template: /pipelines/templates/variables.yml
+extends:
+  template: ../../../../pipelines/templates/stages.yml
+  parameters:
+    buildSteps:
+      - template: /pipelines/templates/build-dotnet.yml@self
+        parameters:
+          project: $(modulePath)/RentalModule.Api/RentalModule.Api.csproj
+      - template: build.yml@shared
+      - template: missing.yml
+"#,
+                ),
+            ],
+        );
+
+        let path = Path::new("CustomerPortal/apis/modules/RentalModule/azure-pipelines.yml");
+        let contents = std::fs::read_to_string(dir.path().join(path)).unwrap();
+        let definition = template::parse_definition(&contents, None).unwrap();
+        let mut walker = DefinitionWalker::new(dir.path());
+        walker.walk(path, &definition, &Scope::default());
+
+        assert_eq!(
+            walker.patterns,
+            vec![
+                "CustomerPortal/apis/modules/RentalModule/RentalModule.Api/RentalModule.Api.csproj"
+            ]
+        );
+        assert_eq!(
+            walker.templates,
+            vec![
+                PathBuf::from("pipelines/templates/variables.yml"),
+                PathBuf::from("pipelines/templates/stages.yml"),
+                PathBuf::from("pipelines/templates/build-dotnet.yml"),
+            ]
+        );
+        assert!(matches!(
+            walker.problems.as_slice(),
+            [Error::IoError { path, .. }]
+                if path == Path::new("pipelines/templates/missing.yml")
+        ));
+    }
 }
@@ -4,52 +4,92 @@ use std::{
 };
 
 use console::style;
-use nom::{
-    bytes::complete::{tag, take_until},
-    character::complete::multispace0,
-    IResult,
-};
+use serde::{Deserialize, Serialize};
 
-use crate::fenced;
+use crate::{
+    evaluation::{self, find_above, EvaluatedProject, DIRECTORY_PACKAGES_PROPS},
+    properties::BuildContext,
+    repo, Result,
+};
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ProjectReference {
+    /// Path of the referenced project, relative to the repository root.
     pub include_path: PathBuf,
+    /// The `Condition` of the reference itself, if any.
+    pub condition: Option<String>,
+    pub private_assets: Option<String>,
+    /// `false` when the reference only enforces build order and does not reference the output.
+    pub reference_output_assembly: Option<bool>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PackageReference {
+    pub name: String,
+    /// The version from `Version`, `VersionOverride` or the central `PackageVersion`, if any.
+    pub version: Option<String>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Project {
+    /// Path of the project file, relative to the repository root.
     pub path: PathBuf,
     pub references: Vec<ProjectReference>,
+    /// `Directory.Build.props`/`.targets` and other files the project imports, relative to the
+    /// repository root. A change to any of them affects the build of the project.
+    pub imports: Vec<PathBuf>,
+    /// Files the project imports that did not exist when it was evaluated.
+    #[serde(default)]
+    pub missing_imports: Vec<PathBuf>,
+    pub packages: Vec<PackageReference>,
+    /// `Directory.Packages.props`, `NuGet.config` and `global.json` files that apply to the
+    /// project, relative to the repository root. They only matter when it references packages.
+    pub package_files: Vec<PathBuf>,
+    /// Whether this is a test project: it sets `IsTestProject`, references a test framework or
+    /// matches one of the configured name patterns.
+    #[serde(default)]
+    pub is_test: bool,
 }
 
+/// Packages only test projects reference.
+const TEST_PACKAGES: [&str; 8] = [
+    "Microsoft.NET.Test.Sdk",
+    "xunit",
+    "xunit.v3",
+    "NUnit",
+    "MSTest",
+    "MSTest.TestFramework",
+    "TUnit",
+    "Microsoft.Testing.Platform",
+];
+
 impl Display for Project {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         writeln!(
             f,
-            "\n    {}, {} deps:",
+            "\n    {}{}, {} deps:",
             style(project_name_from_path(&self.path)).cyan().italic(),
+            if self.is_test {
+                style(" (test)").dim()
+            } else {
+                style("")
+            },
             style(self.references.len()).yellow().bold()
         )?;
 
-        self.references
-            .clone()
-            .into_iter()
-            .enumerate()
-            .for_each(|(i, project_reference)| {
-                write!(
-                    f,
-                    "        {}: {}{}",
-                    style(i + 1).bold(),
-                    style(project_name_from_path(&project_reference.include_path)).dim(),
-                    if i < self.references.len() - 1 {
-                        "\n"
-                    } else {
-                        ""
-                    }
-                )
-                .unwrap();
-            });
+        for (i, project_reference) in self.references.iter().enumerate() {
+            write!(
+                f,
+                "        {}: {}{}",
+                style(i + 1).bold(),
+                style(project_name_from_path(&project_reference.include_path)).dim(),
+                if i < self.references.len() - 1 {
+                    "\n"
+                } else {
+                    ""
+                }
+            )?;
+        }
 
         Ok(())
     }
@@ -57,43 +97,59 @@ impl Display for Project {
 
 fn project_name_from_path(path: &Path) -> String {
     path.file_name()
-        .unwrap()
-        .to_str()
-        .unwrap()
+        .unwrap_or_default()
+        .to_string_lossy()
         .trim_end_matches(".csproj")
         .to_string()
 }
 
 impl Project {
-    pub fn new(path: PathBuf) -> Self {
-        let includes = extract_includes(&path);
+    pub fn new(repo_root: &Path, path: PathBuf, context: &BuildContext) -> Result<Self> {
+        let path = repo::relative_to(repo_root, &path);
+        repo::to_str(&path)?;
+        let evaluated = evaluation::evaluate(repo_root, &path, context)?;
+        let references = extract_references(repo_root, &path, &evaluated);
+        let packages = extract_packages(&evaluated);
+        let is_test = evaluated
+            .properties
+            .get("IsTestProject")
+            .is_some_and(|value| value.trim().eq_ignore_ascii_case("true"))
+            || packages.iter().any(|package| {
+                TEST_PACKAGES
+                    .iter()
+                    .any(|test_package| package.name.eq_ignore_ascii_case(test_package))
+            });
 
-        Self {
+        let (mut package_files, imports): (Vec<_>, Vec<_>) =
+            evaluated.imports.into_iter().partition(|import| {
+                import
+                    .file_name()
+                    .is_some_and(|name| name == DIRECTORY_PACKAGES_PROPS)
+            });
+        package_files.extend(find_package_config_files(repo_root, &path));
+
+        Ok(Self {
             path,
-            references: includes
-                .into_iter()
-                .map(|include| ProjectReference {
-                    include_path: include,
-                })
-                .collect(),
-        }
+            references,
+            imports,
+            missing_imports: evaluated.missing_imports,
+            packages,
+            package_files,
+            is_test,
+        })
     }
 
-    pub fn azure_path_filter(&self) -> String {
-        let path = self.path.to_str().unwrap();
-        let (input, _) = take_until::<_, _, ()>("SE-CustomerPortal")(path).unwrap();
-        let (base_path, _) = tag::<_, _, ()>("SE-CustomerPortal")(input).unwrap();
+    /// The file name without the extension, e.g. `Shared.Core` for `Shared/Shared.Core.csproj`.
+    pub fn name(&self) -> String {
+        self.path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_default()
+    }
 
+    pub fn azure_path_filter(&self) -> String {
         // replace the file name with a wildcard
-        PathBuf::from(base_path)
-            .parent()
-            .unwrap()
-            .join("*")
-            .to_str()
-            .unwrap()
-            .replace('\\', "/")
-            .to_string()
-            + ";"
+        repo::azure_path(&self.path.parent().unwrap_or(Path::new("")).join("*")) + ";"
     }
 
     pub fn pretty_print(&self) {
@@ -117,55 +173,97 @@ impl Project {
     }
 }
 
-fn extract_include(input: &str) -> IResult<&str, String> {
-    let (input, _) = multispace0(input)?;
-    let (input, _) = tag("<ProjectReference")(input)?;
-    let (input, _) = multispace0(input)?;
-    let (input, _) = tag("Include=")(input)?;
+fn extract_references(
+    repo_root: &Path,
+    project_path: &Path,
+    evaluated: &EvaluatedProject,
+) -> Vec<ProjectReference> {
+    let project_dir = project_path.parent().unwrap_or(Path::new(""));
 
-    let (input, path) = fenced("\"", "\"")(input)?;
+    let mut references = Vec::new();
+    for item in evaluated.items("ProjectReference") {
+        references.push(ProjectReference {
+            include_path: repo::relative_to(
+                repo_root,
+                &project_dir.join(item.include.replace('\\', "/")),
+            ),
+            condition: item.condition.clone(),
+            private_assets: item.metadata("PrivateAssets").map(str::to_string),
+            reference_output_assembly: item
+                .metadata("ReferenceOutputAssembly")
+                .map(|value| value.trim().eq_ignore_ascii_case("true")),
+        });
+    }
 
-    Ok((input, path.to_string()))
+    references
 }
 
-fn extract_includes(project_path: &Path) -> Vec<PathBuf> {
-    let input = std::fs::read_to_string(project_path)
-        .unwrap_or_else(|_| panic!("Failed to read project file at {}", project_path.display()));
+/// The `PackageReference`s of the project and the `GlobalPackageReference`s that Central Package
+/// Management adds to every project, with their versions resolved.
+fn extract_packages(evaluated: &EvaluatedProject) -> Vec<PackageReference> {
+    let central = evaluated
+        .properties
+        .get("ManagePackageVersionsCentrally")
+        .is_some_and(|value| value.trim().eq_ignore_ascii_case("true"));
+    let central_version = |name: &str| {
+        evaluated
+            .items("PackageVersion")
+            .filter(|item| item.include.eq_ignore_ascii_case(name))
+            .last()
+            .and_then(|item| item.metadata("Version"))
+            .map(str::to_string)
+    };
 
-    let mut includes = Vec::new();
-    for line in input.lines() {
-        // We don't care about the tests!
-        if line.contains("Tests.csproj") || line.contains("Test.csproj") || line.contains(".Test") {
+    let mut packages = Vec::<PackageReference>::new();
+    for item in evaluated
+        .items("PackageReference")
+        .chain(evaluated.items("GlobalPackageReference"))
+    {
+        if packages
+            .iter()
+            .any(|package| package.name.eq_ignore_ascii_case(&item.include))
+        {
             continue;
         }
 
-        if let Ok((_, include)) = extract_include(line) {
-            includes.push(include);
-        }
+        // `GlobalPackageReference`s carry their own version even with central versions.
+        let version = item
+            .metadata("VersionOverride")
+            .map(str::to_string)
+            .or_else(|| central.then(|| central_version(&item.include)).flatten())
+            .or_else(|| item.metadata("Version").map(str::to_string));
+        packages.push(PackageReference {
+            name: item.include.clone(),
+            version: version.map(|version| evaluated.properties.expand(&version)),
+        });
     }
 
-    let mut resolved_paths = Vec::new();
-    for include in includes {
-        let mut path = project_path.to_path_buf();
-        path.pop(); // pop from file to directory
-        let components: Vec<&str> = include.split('\\').collect();
-        for component in components {
-            if component == ".." {
-                path.pop();
-            } else {
-                path.push(component);
-            }
-        }
+    packages
+}
 
-        resolved_paths.push(path);
-    }
+/// Every `NuGet.config` from the project's directory up to the repository root, since NuGet
+/// merges all of them, and the nearest `global.json`.
+fn find_package_config_files(repo_root: &Path, project_path: &Path) -> Vec<PathBuf> {
+    let project_dir = project_path.parent().unwrap_or(Path::new(""));
 
-    resolved_paths
+    let mut files = project_dir
+        .ancestors()
+        .filter_map(|directory| {
+            ["NuGet.config", "NuGet.Config", "nuget.config"]
+                .iter()
+                .map(|name| directory.join(name))
+                .find(|path| repo_root.join(path).is_file())
+        })
+        .collect::<Vec<_>>();
+    files.extend(find_above(repo_root, project_dir, "global.json"));
+
+    files
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::testing::write_tree;
 
     #[test]
     fn test_extract_includes() {
@@ -209,20 +307,114 @@ mod tests {
         </Project>
         "#;
 
-        let mut includes = Vec::new();
-        for line in input.lines() {
-            if let Ok((_, include)) = extract_include(line) {
-                includes.push(include);
-            }
-        }
+        let dir = tempfile::tempdir().unwrap();
+        let project_path = "CustomerPortal/apis/modules/Functions/Functions.csproj";
+        write_tree(dir.path(), &[(project_path, input)]);
+
+        let includes = Project::new(
+            dir.path(),
+            dir.path().join(project_path),
+            &BuildContext::default(),
+        )
+        .unwrap()
+        .references
+        .into_iter()
+        .map(|reference| reference.include_path)
+        .collect::<Vec<_>>();
 
         assert_eq!(
             includes,
             vec![
-                r#"..\..\SharedLibraries\Shared.Api.ServiceBus\Shared.Api.ServiceBus.csproj"#,
-                r#"..\..\SharedLibraries\Shared.Infrastructure\Shared.Infrastructure.csproj"#,
-                r#"..\WashingMachine\WashingMachine\WashingMachine.csproj"#
+                PathBuf::from("CustomerPortal/apis/SharedLibraries/Shared.Api.ServiceBus/Shared.Api.ServiceBus.csproj"),
+                PathBuf::from("CustomerPortal/apis/SharedLibraries/Shared.Infrastructure/Shared.Infrastructure.csproj"),
+                PathBuf::from(
+                    "CustomerPortal/apis/modules/WashingMachine/WashingMachine/WashingMachine.csproj"
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_extract_packages() {
+        let dir = tempfile::tempdir().unwrap();
+        write_tree(
+            dir.path(),
+            &[
+                (
+                    "Directory.Packages.props",
+                    r#"<Project>
+  <PropertyGroup>
+    <ManagePackageVersionsCentrally>true</ManagePackageVersionsCentrally>
+    <AzureStorageVersion>12.17.0</AzureStorageVersion>
+  </PropertyGroup>
+  <ItemGroup>
+    <PackageVersion Include="Azure.Storage.Blobs" Version="$(AzureStorageVersion)" />
+    <PackageVersion Include="Serilog" Version="3.0.1" />
+    <GlobalPackageReference Include="StyleCop.Analyzers" Version="1.1.118" />
+  </ItemGroup>
+</Project>"#,
+                ),
+                ("NuGet.config", "<configuration />"),
+                ("global.json", "{}"),
+                (
+                    "CustomerPortal/apis/Api/Api.csproj",
+                    r#"<Project Sdk="Microsoft.NET.Sdk">
+  <ItemGroup>
+    <PackageReference Include="azure.storage.blobs" />
+    <PackageReference Include="Serilog" VersionOverride="3.1.0" />
+  </ItemGroup>
+</Project>"#,
+                ),
+            ],
+        );
+
+        let project = Project::new(
+            dir.path(),
+            PathBuf::from("CustomerPortal/apis/Api/Api.csproj"),
+            &BuildContext::default(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            project
+                .packages
+                .iter()
+                .map(|package| (package.name.as_str(), package.version.as_deref()))
+                .collect::<Vec<_>>(),
+            vec![
+                ("azure.storage.blobs", Some("12.17.0")),
+                ("Serilog", Some("3.1.0")),
+                ("StyleCop.Analyzers", Some("1.1.118")),
+            ]
+        );
+        assert!(project.imports.is_empty());
+        assert_eq!(
+            project.package_files,
+            vec![
+                PathBuf::from("Directory.Packages.props"),
+                PathBuf::from("NuGet.config"),
+                PathBuf::from("global.json"),
             ]
         );
     }
+
+    #[test]
+    fn test_azure_path_filter() {
+        let project = Project {
+            path: PathBuf::from(
+                "CustomerPortal/apis/modules/RentalModule/RentalModule.Api/RentalModule.Api.csproj",
+            ),
+            references: Vec::new(),
+            imports: Vec::new(),
+            missing_imports: Vec::new(),
+            packages: Vec::new(),
+            package_files: Vec::new(),
+            is_test: false,
+        };
+
+        assert_eq!(
+            project.azure_path_filter(),
+            "/CustomerPortal/apis/modules/RentalModule/RentalModule.Api/*;"
+        );
+    }
 }
@@ -0,0 +1,313 @@
+//! MSBuild properties and their expansion in conditions and item includes.
+
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf, MAIN_SEPARATOR},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{fenced, repo, solution::is_solution_file};
+
+/// The global properties a build is evaluated with, i.e. what `dotnet build` receives through
+/// `--configuration` and `-p:Platform=`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BuildContext {
+    pub configuration: String,
+    pub platform: String,
+}
+
+impl Default for BuildContext {
+    fn default() -> Self {
+        Self {
+            configuration: "Release".to_string(),
+            platform: "AnyCPU".to_string(),
+        }
+    }
+}
+
+impl BuildContext {
+    /// The properties every project starts out with: the environment variables and the global
+    /// properties, which cannot be overridden.
+    pub fn properties(&self) -> Properties {
+        let mut properties = Properties::default();
+        for (name, value) in std::env::vars() {
+            properties.set(&name, value);
+        }
+        properties.set_read_only("Configuration", self.configuration.clone());
+        properties.set_read_only("Platform", self.platform.clone());
+        properties
+    }
+
+    /// The properties of the project at `project_path` (relative to `repo_root`) before any of its
+    /// own property groups are evaluated, including the reserved `MSBuildProject*` properties.
+    pub fn project_properties(&self, repo_root: &Path, project_path: &Path) -> Properties {
+        let full_path = repo_root.join(project_path);
+        let directory = full_path.parent().unwrap_or(repo_root);
+
+        let mut properties = self.properties();
+        properties.set_read_only("MSBuildProjectFullPath", path_string(&full_path));
+        properties.set_read_only("MSBuildProjectDirectory", path_string(directory));
+        properties.set_read_only("MSBuildProjectFile", file_name(&full_path));
+        properties.set_read_only("MSBuildProjectName", file_stem(&full_path));
+        properties.set_read_only("MSBuildProjectExtension", extension(&full_path));
+        properties.set_this_file(&full_path);
+
+        properties.set(
+            "SolutionDir",
+            directory_string(&repo_root.join(solution_dir(repo_root, project_path))),
+        );
+
+        properties
+    }
+}
+
+/// The directory `SolutionDir` points to for the project at `project_path`, relative to
+/// `repo_root`. It is only defined when building a solution, so the nearest directory with a
+/// solution above the project is assumed, or the repository root if there is none.
+pub fn solution_dir(repo_root: &Path, project_path: &Path) -> PathBuf {
+    project_path
+        .parent()
+        .unwrap_or(Path::new(""))
+        .ancestors()
+        .find(|dir| has_solution(&repo_root.join(dir)))
+        .unwrap_or(Path::new(""))
+        .to_path_buf()
+}
+
+/// Evaluated properties, looked up case-insensitively like MSBuild does.
+#[derive(Debug, Clone, Default)]
+pub struct Properties {
+    values: HashMap<String, String>,
+    /// Global and reserved properties, which a project cannot change.
+    read_only: HashSet<String>,
+}
+
+impl Properties {
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.values.get(&name.to_lowercase()).map(String::as_str)
+    }
+
+    pub fn set(&mut self, name: &str, value: String) {
+        let name = name.to_lowercase();
+        if !self.read_only.contains(&name) {
+            self.values.insert(name, value);
+        }
+    }
+
+    fn set_read_only(&mut self, name: &str, value: String) {
+        let name = name.to_lowercase();
+        self.values.insert(name.clone(), value);
+        self.read_only.insert(name);
+    }
+
+    /// Points the `MSBuildThisFile*` properties at the file currently being evaluated.
+    pub fn set_this_file(&mut self, full_path: &Path) {
+        let directory = full_path.parent().unwrap_or(Path::new(""));
+        self.set_read_only("MSBuildThisFileFullPath", path_string(full_path));
+        self.set_read_only("MSBuildThisFileDirectory", directory_string(directory));
+        self.set_read_only("MSBuildThisFile", file_name(full_path));
+        self.set_read_only("MSBuildThisFileName", file_stem(full_path));
+        self.set_read_only("MSBuildThisFileExtension", extension(full_path));
+    }
+
+    /// Replaces `$(Name)` references with their values. Undefined properties expand to an empty
+    /// string, as in MSBuild. Of the property functions (`$([...])`) only the file lookups used
+    /// to chain `Directory.Build.props` files are evaluated, others are left untouched.
+    pub fn expand(&self, input: &str) -> String {
+        let mut expanded = String::new();
+        let mut rest = input;
+        while let Some(start) = rest.find("$(") {
+            expanded.push_str(&rest[..start]);
+            let candidate = &rest[start..];
+
+            if let Some(end) = candidate
+                .starts_with("$([")
+                .then(|| closing_parenthesis(candidate))
+                .flatten()
+            {
+                match self.property_function(&candidate[2..end]) {
+                    Some(value) => expanded.push_str(&value),
+                    None => expanded.push_str(&candidate[..=end]),
+                }
+                rest = &candidate[end + 1..];
+                continue;
+            }
+
+            match fenced("$(", ")")(candidate) {
+                Ok((remaining, name)) if !name.starts_with('[') => {
+                    expanded.push_str(self.get(name.trim()).unwrap_or_default());
+                    rest = remaining;
+                }
+                _ => {
+                    expanded.push_str("$(");
+                    rest = &candidate[2..];
+                }
+            }
+        }
+        expanded.push_str(rest);
+
+        expanded
+    }
+
+    /// Evaluates `[MSBuild]::GetPathOfFileAbove(file, startingDirectory)` and
+    /// `[MSBuild]::GetDirectoryNameOfFileAbove(startingDirectory, file)`.
+    fn property_function(&self, function: &str) -> Option<String> {
+        let (name, arguments) = function.split_once("]::")?;
+        if !name.eq_ignore_ascii_case("[MSBuild") {
+            return None;
+        }
+        let (name, arguments) = arguments.strip_suffix(')')?.split_once('(')?;
+        let arguments = arguments
+            .split(',')
+            .map(|argument| {
+                self.expand(argument.trim().trim_matches('\''))
+                    .replace('\\', "/")
+            })
+            .collect::<Vec<_>>();
+        let this_file_directory = self.get("MSBuildThisFileDirectory").unwrap_or_default();
+
+        match name.trim().to_lowercase().as_str() {
+            "getpathoffileabove" => {
+                let start = arguments.get(1).map_or(this_file_directory, String::as_str);
+                let directory = directory_of_file_above(Path::new(start), &arguments[0]);
+                Some(
+                    directory
+                        .map(|directory| path_string(&directory.join(&arguments[0])))
+                        .unwrap_or_default(),
+                )
+            }
+            "getdirectorynameoffileabove" => {
+                let directory =
+                    directory_of_file_above(Path::new(&arguments[0]), arguments.get(1)?);
+                Some(
+                    directory
+                        .map(|directory| path_string(&directory))
+                        .unwrap_or_default(),
+                )
+            }
+            _ => None,
+        }
+    }
+}
+
+fn directory_of_file_above(start: &Path, file_name: &str) -> Option<PathBuf> {
+    let start = repo::normalize(start);
+    start
+        .ancestors()
+        .find(|directory| directory.join(file_name).is_file())
+        .map(Path::to_path_buf)
+}
+
+/// Finds the parenthesis closing the one at index 1, skipping over quoted strings.
+fn closing_parenthesis(input: &str) -> Option<usize> {
+    let mut depth = 0;
+    let mut quoted = false;
+    for (index, c) in input.char_indices().skip(1) {
+        match c {
+            '\'' => quoted = !quoted,
+            '(' if !quoted => depth += 1,
+            ')' if !quoted => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(index);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    None
+}
+
+/// Property values are strings, so a path that is not valid UTF-8 is converted lossily rather
+/// than failing the whole evaluation.
+fn path_string(path: &Path) -> String {
+    repo::normalize(path).to_string_lossy().into_owned()
+}
+
+/// Directory properties such as `MSBuildThisFileDirectory` end with a separator.
+fn directory_string(path: &Path) -> String {
+    let mut directory = path_string(path);
+    if !directory.ends_with(MAIN_SEPARATOR) {
+        directory.push(MAIN_SEPARATOR);
+    }
+    directory
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or_default()
+        .to_string()
+}
+
+fn file_stem(path: &Path) -> String {
+    path.file_stem()
+        .and_then(|name| name.to_str())
+        .unwrap_or_default()
+        .to_string()
+}
+
+fn extension(path: &Path) -> String {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .map(|extension| format!(".{}", extension))
+        .unwrap_or_default()
+}
+
+fn has_solution(dir: &Path) -> bool {
+    std::fs::read_dir(dir).is_ok_and(|entries| {
+        entries
+            .filter_map(Result::ok)
+            .any(|entry| is_solution_file(&entry.file_name().to_string_lossy()))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::write_tree;
+
+    #[test]
+    fn test_expand() {
+        let properties = BuildContext::default().properties();
+
+        assert_eq!(
+            properties.expand("$(configuration)|$(Platform)|$(Undefined)"),
+            "Release|AnyCPU|"
+        );
+        assert_eq!(
+            properties.expand("$([System.IO.Path]::Combine('a', 'b'))"),
+            "$([System.IO.Path]::Combine('a', 'b'))"
+        );
+    }
+
+    #[test]
+    fn test_project_properties() {
+        let repo_root = Path::new("/repo");
+        let mut properties = BuildContext::default()
+            .project_properties(repo_root, Path::new("CustomerPortal/apis/Api/Api.csproj"));
+        properties.set("Configuration", "Debug".to_string());
+
+        assert_eq!(properties.get("Configuration"), Some("Release"));
+        assert_eq!(properties.get("MSBuildProjectName"), Some("Api"));
+        assert_eq!(
+            PathBuf::from(properties.expand("$(MSBuildThisFileDirectory)")),
+            PathBuf::from("/repo/CustomerPortal/apis/Api/")
+        );
+        assert_eq!(
+            PathBuf::from(properties.expand("$(SolutionDir)")),
+            PathBuf::from("/repo/")
+        );
+
+        let dir = tempfile::tempdir().unwrap();
+        write_tree(dir.path(), &[("Backend/Backend.slnx", "<Solution />")]);
+        let properties = BuildContext::default()
+            .project_properties(dir.path(), Path::new("Backend/src/Api/Api.csproj"));
+        assert_eq!(
+            PathBuf::from(properties.expand("$(SolutionDir)")),
+            dir.path().join("Backend/")
+        );
+    }
+}
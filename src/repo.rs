@@ -0,0 +1,112 @@
+use std::path::{Component, Path, PathBuf};
+
+use crate::{Error, Result};
+
+/// Finds the repository root by walking up from `start` until a directory containing `.git` is
+/// found. Worktrees and submodules use a `.git` file rather than a directory, so both count.
+pub fn find_root(start: &Path) -> Option<PathBuf> {
+    let start = normalize(start);
+    start
+        .ancestors()
+        .find(|dir| dir.join(".git").exists())
+        .map(Path::to_path_buf)
+}
+
+/// Expresses `path` relative to `root`. Paths that are already relative are only normalized.
+pub fn relative_to(root: &Path, path: &Path) -> PathBuf {
+    let path = normalize(path);
+    match path.strip_prefix(normalize(root)) {
+        Ok(relative) => relative.to_path_buf(),
+        Err(_) => path,
+    }
+}
+
+/// Lexically resolves `.` and `..` components without touching the file system, so that paths
+/// built from project references compare equal regardless of how they were spelled.
+pub fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if !normalized.pop() {
+                    normalized.push("..");
+                }
+            }
+            other => normalized.push(other.as_os_str()),
+        }
+    }
+
+    normalized
+}
+
+/// Formats a repository relative path the way Azure DevOps expects it: forward slashes and a
+/// leading `/`. Pipelines and projects are checked with [`to_str`] when they are loaded, so the
+/// lossy conversion never kicks in.
+pub fn azure_path(path: &Path) -> String {
+    format!("/{}", path.to_string_lossy().replace('\\', "/"))
+}
+
+/// The path as a string, or an error if it is not valid UTF-8.
+pub fn to_str(path: &Path) -> Result<&str> {
+    path.to_str().ok_or_else(|| Error::InvalidPath {
+        path: path.to_path_buf(),
+    })
+}
+
+/// The files that differ between `base` and the working tree, relative to the repository root,
+/// as reported by `git diff --name-only`. Needs `git` on the `PATH`.
+pub fn changed_files(repo_root: &Path, base: &str) -> Result<Vec<PathBuf>> {
+    let output = std::process::Command::new("git")
+        .arg("-C")
+        .arg(repo_root)
+        .args(["diff", "--name-only", "-z", base, "--"])
+        .output()
+        .map_err(|e| Error::GitError {
+            message: e.to_string(),
+        })?;
+    if !output.status.success() {
+        return Err(Error::GitError {
+            message: String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        });
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .split('\0')
+        .filter(|path| !path.is_empty())
+        .map(PathBuf::from)
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize() {
+        assert_eq!(
+            normalize(Path::new(
+                "CustomerPortal/apis/Api/../../SharedLibraries/./Shared"
+            )),
+            PathBuf::from("CustomerPortal/SharedLibraries/Shared")
+        );
+        assert_eq!(
+            normalize(Path::new("../Shared")),
+            PathBuf::from("../Shared")
+        );
+    }
+
+    #[test]
+    fn test_find_root() {
+        let dir = tempfile::tempdir().unwrap();
+        let nested = dir.path().join("CustomerPortal").join("apis");
+        std::fs::create_dir_all(&nested).unwrap();
+        std::fs::create_dir(dir.path().join(".git")).unwrap();
+
+        assert_eq!(find_root(&nested), Some(dir.path().to_path_buf()));
+        assert_eq!(
+            relative_to(dir.path(), &nested),
+            PathBuf::from("CustomerPortal/apis")
+        );
+    }
+}
@@ -0,0 +1,224 @@
+//! Visual Studio solutions, `.sln` and the XML based `.slnx`, which pipelines can build instead
+//! of individual projects.
+
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+};
+
+use roxmltree::Document;
+
+use crate::{definition::is_project_file, error, properties::BuildContext, repo, Error, Result};
+
+pub(crate) fn is_solution_file(path: &str) -> bool {
+    let path = path.to_ascii_lowercase();
+    path.ends_with(".sln") || path.ends_with(".slnx")
+}
+
+/// The projects of the solution at `path` that are built in the configuration and platform of
+/// `context`, relative to the repository root. Solution folders and projects other than C#
+/// projects are left out.
+pub fn read_projects(
+    repo_root: &Path,
+    path: &Path,
+    context: &BuildContext,
+) -> Result<Vec<PathBuf>> {
+    let contents = error::read_to_string(repo_root, path)?;
+    let projects = if path
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("slnx"))
+    {
+        parse_slnx(&contents, context).map_err(|e| Error::xml(path, e))?
+    } else {
+        parse_sln(&contents, context)
+    };
+
+    let directory = path.parent().unwrap_or(Path::new(""));
+    Ok(projects
+        .iter()
+        .filter(|project| is_project_file(project))
+        .map(|project| repo::relative_to(repo_root, &directory.join(project.replace('\\', "/"))))
+        .collect())
+}
+
+/// The project paths of a `.sln` file. A project is built when the solution configuration
+/// matching `context` has a `Build.0` entry for it. Without a matching solution configuration,
+/// every project is.
+fn parse_sln(contents: &str, context: &BuildContext) -> Vec<String> {
+    let mut projects = Vec::new();
+    let mut configurations = Vec::new();
+    let mut built = HashSet::new();
+    let mut section = "";
+    for line in contents.lines().map(str::trim) {
+        // Project("{type}") = "Name", "Path\Name.csproj", "{guid}"
+        if let Some((_, values)) = line
+            .strip_prefix("Project(")
+            .and_then(|line| line.split_once('='))
+        {
+            let values = values
+                .split(',')
+                .map(|value| value.trim().trim_matches('"'))
+                .collect::<Vec<_>>();
+            if let [_, path, guid] = values[..] {
+                projects.push((guid.to_ascii_uppercase(), path.to_string()));
+            }
+        } else if let Some(name) = line.strip_prefix("GlobalSection(") {
+            section = name.split(')').next().unwrap_or_default();
+        } else if line == "EndGlobalSection" {
+            section = "";
+        } else if let Some((key, _)) = line.split_once('=') {
+            match section {
+                // Release|Any CPU = Release|Any CPU
+                "SolutionConfigurationPlatforms" => configurations.push(key.trim().to_string()),
+                // {guid}.Release|Any CPU.Build.0 = Release|Any CPU
+                "ProjectConfigurationPlatforms" => {
+                    let entry = key.trim().strip_suffix(".Build.0");
+                    if let Some((guid, configuration)) = entry.and_then(|e| e.split_once('.')) {
+                        built.insert((guid.to_ascii_uppercase(), configuration.to_string()));
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    let mut selected = configurations
+        .iter()
+        .filter(|name| selects(name, context) == (true, true))
+        .collect::<Vec<_>>();
+    if selected.is_empty() {
+        // The platform is often spelled differently, e.g. `x64` in the solution for `AnyCPU`.
+        selected = configurations
+            .iter()
+            .filter(|name| selects(name, context).0)
+            .collect();
+    }
+
+    projects
+        .into_iter()
+        .filter(|(guid, _)| {
+            selected.is_empty()
+                || selected
+                    .iter()
+                    .any(|name| built.contains(&(guid.clone(), name.to_string())))
+        })
+        .map(|(_, path)| path)
+        .collect()
+}
+
+/// The project paths of a `.slnx` file, without the projects a `<Build Project="false" />`
+/// excludes in the solution configuration matching `context`.
+fn parse_slnx(contents: &str, context: &BuildContext) -> Result<Vec<String>, roxmltree::Error> {
+    let document = Document::parse(contents.trim_start_matches('\u{feff}'))?;
+
+    Ok(document
+        .descendants()
+        .filter(|node| node.has_tag_name("Project"))
+        .filter(|project| {
+            !project.children().any(|build| {
+                build.has_tag_name("Build")
+                    && build
+                        .attribute("Project")
+                        .is_some_and(|value| value.eq_ignore_ascii_case("false"))
+                    && build
+                        .attribute("Solution")
+                        .is_none_or(|name| selects(name, context) == (true, true))
+            })
+        })
+        .filter_map(|project| project.attribute("Path"))
+        .map(str::to_string)
+        .collect())
+}
+
+/// Whether the configuration and the platform of the solution configuration `name`, e.g.
+/// `Release|Any CPU`, are the ones of `context`. `*` matches anything.
+fn selects(name: &str, context: &BuildContext) -> (bool, bool) {
+    let (configuration, platform) = name.split_once('|').unwrap_or((name, "*"));
+    let normalize = |platform: &str| platform.replace(' ', "").to_ascii_lowercase();
+
+    (
+        configuration == "*"
+            || configuration
+                .trim()
+                .eq_ignore_ascii_case(&context.configuration),
+        platform == "*" || normalize(platform) == normalize(&context.platform),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::write_tree;
+
+    #[test]
+    fn test_read_projects() {
+        let dir = tempfile::tempdir().unwrap();
+        write_tree(
+            dir.path(),
+            &[
+                (
+                    "Backend/Backend.sln",
+                    r#"
+Microsoft Visual Studio Solution File, Format Version 12.00
+Project("{2150E333-8FDC-42A3-9474-1A3956D46DE8}") = "src", "src", "{00000000-0000-0000-0000-000000000001}"
+EndProject
+Project("{FAE04EC0-301F-11D3-BF4B-00C04F79EFBC}") = "Api", "src\Api\Api.csproj", "{00000000-0000-0000-0000-000000000002}"
+EndProject
+Project("{FAE04EC0-301F-11D3-BF4B-00C04F79EFBC}") = "Tool", "..\Tools\Tool\Tool.csproj", "{00000000-0000-0000-0000-000000000003}"
+EndProject
+Global
+	GlobalSection(SolutionConfigurationPlatforms) = preSolution
+		Debug|Any CPU = Debug|Any CPU
+		Release|Any CPU = Release|Any CPU
+	EndGlobalSection
+	GlobalSection(ProjectConfigurationPlatforms) = postSolution
+		{00000000-0000-0000-0000-000000000002}.Debug|Any CPU.ActiveCfg = Debug|Any CPU
+		{00000000-0000-0000-0000-000000000002}.Debug|Any CPU.Build.0 = Debug|Any CPU
+		{00000000-0000-0000-0000-000000000002}.Release|Any CPU.ActiveCfg = Release|Any CPU
+		{00000000-0000-0000-0000-000000000002}.Release|Any CPU.Build.0 = Release|Any CPU
+		{00000000-0000-0000-0000-000000000003}.Debug|Any CPU.ActiveCfg = Debug|Any CPU
+		{00000000-0000-0000-0000-000000000003}.Debug|Any CPU.Build.0 = Debug|Any CPU
+		{00000000-0000-0000-0000-000000000003}.Release|Any CPU.ActiveCfg = Release|Any CPU
+	EndGlobalSection
+EndGlobal
+"#,
+                ),
+                (
+                    "Backend/Backend.slnx",
+                    r#"<Solution>
+  <Configurations>
+    <Platform Name="Any CPU" />
+  </Configurations>
+  <Folder Name="/src/">
+    <Project Path="src/Api/Api.csproj" />
+  </Folder>
+  <Project Path="../Tools/Tool/Tool.csproj">
+    <Build Solution="Release|*" Project="false" />
+  </Project>
+  <Project Path="web/web.esproj" />
+</Solution>"#,
+                ),
+            ],
+        );
+
+        let release = BuildContext::default();
+        let debug = BuildContext {
+            configuration: "Debug".to_string(),
+            ..BuildContext::default()
+        };
+        for solution in ["Backend/Backend.sln", "Backend/Backend.slnx"] {
+            let projects = |context| read_projects(dir.path(), Path::new(solution), context);
+            assert_eq!(
+                projects(&release).unwrap(),
+                vec![PathBuf::from("Backend/src/Api/Api.csproj")]
+            );
+            assert_eq!(
+                projects(&debug).unwrap(),
+                vec![
+                    PathBuf::from("Backend/src/Api/Api.csproj"),
+                    PathBuf::from("Tools/Tool/Tool.csproj")
+                ]
+            );
+        }
+    }
+}
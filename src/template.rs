@@ -0,0 +1,281 @@
+//! Loading of pipeline definitions and the templates they include. Template parameters are
+//! substituted on the raw YAML before it is turned into a [`Definition`], so an included template
+//! is walked exactly like the pipeline that includes it.
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use serde_yaml::{Mapping, Value};
+
+use crate::{
+    definition::{Definition, Parameters},
+    error, repo,
+    variables::Scope,
+    Error, Result,
+};
+
+/// A template file, loaded with the parameters of one particular include.
+#[derive(Debug)]
+pub struct Template {
+    /// Path of the template file, relative to the repository root.
+    pub path: PathBuf,
+    pub definition: Definition,
+}
+
+/// Loads the template `reference` included from `including_file`. `arguments` are the
+/// `parameters:` given at the include site, expanded in the `caller` scope. Returns `None` for a
+/// template from another repository resource that is not present in this one.
+pub fn load(
+    repo_root: &Path,
+    including_file: &Path,
+    reference: &str,
+    arguments: Option<&Value>,
+    caller: &Scope,
+) -> Result<Option<Template>> {
+    let path = resolve_path(including_file, reference);
+    if is_external(reference) && !repo_root.join(&path).is_file() {
+        return Ok(None);
+    }
+    let contents = error::read_to_string(repo_root, &path)?;
+
+    let arguments =
+        arguments.map(|arguments| substitute(arguments.clone(), caller, &HashMap::new()));
+    let definition =
+        parse_definition(&contents, arguments.as_ref()).map_err(|e| Error::yaml(&path, e))?;
+
+    Ok(Some(Template { path, definition }))
+}
+
+/// Resolves a `template:` reference to a path relative to the repository root. Plain and `@self`
+/// references are relative to the including file, references into repository resources are
+/// treated as paths from the root of this repository.
+pub fn resolve_path(including_file: &Path, reference: &str) -> PathBuf {
+    let (path, repository) = match reference.rsplit_once('@') {
+        Some((path, repository)) => (path, Some(repository.trim())),
+        None => (reference, None),
+    };
+    let path = path.trim().replace('\\', "/");
+
+    match (path.strip_prefix('/'), repository) {
+        (Some(path), _) => repo::normalize(Path::new(path)),
+        (None, Some(repository)) if repository != "self" => repo::normalize(Path::new(&path)),
+        _ => repo::normalize(&including_file.parent().unwrap_or(Path::new("")).join(path)),
+    }
+}
+
+/// Whether `reference` points into a repository resource other than `self`.
+fn is_external(reference: &str) -> bool {
+    reference
+        .rsplit_once('@')
+        .is_some_and(|(_, repository)| repository.trim() != "self")
+}
+
+/// Parses a pipeline or template, replacing `${{ parameters.x }}` with the given `arguments` or
+/// the declared defaults. Conditional insertions (`${{ if ... }}:`) are kept regardless of their
+/// condition, since a path filter has to cover every branch.
+pub fn parse_definition(
+    contents: &str,
+    arguments: Option<&Value>,
+) -> Result<Definition, serde_yaml::Error> {
+    let value: Value = serde_yaml::from_str(contents)?;
+    let Value::Mapping(mapping) = value else {
+        return Ok(Definition::default());
+    };
+
+    let mut parameters = HashMap::new();
+    if let Some(declared) = mapping.get("parameters") {
+        let declared: Parameters = serde_yaml::from_value(declared.clone())?;
+        for (name, default) in declared.defaults() {
+            parameters.insert(name.to_lowercase(), default);
+        }
+    }
+    if let Some(Value::Mapping(arguments)) = arguments {
+        for (name, value) in arguments {
+            if let Some(name) = name.as_str() {
+                parameters.insert(name.to_lowercase(), value.clone());
+            }
+        }
+    }
+
+    let scope = Scope::default().with_parameters(parameters.clone());
+    let mut substituted = Mapping::new();
+    for (key, value) in mapping {
+        let value = if key.as_str() == Some("parameters") {
+            value
+        } else {
+            substitute(value, &scope, &parameters)
+        };
+        substituted.insert(key, value);
+    }
+
+    serde_yaml::from_value(Value::Mapping(substituted))
+}
+
+/// Expands every string in `value` in `scope`. A string consisting of a single
+/// `${{ parameters.x }}` whose value is a list or an object is replaced by that value, which is
+/// how step lists and other structured parameters are passed into templates. Lists used as an
+/// item of another list are spliced into it.
+fn substitute(value: Value, scope: &Scope, parameters: &HashMap<String, Value>) -> Value {
+    match value {
+        Value::String(string) => {
+            match parameter_reference(&string).and_then(|name| parameters.get(&name)) {
+                Some(value @ (Value::Sequence(_) | Value::Mapping(_))) => value.clone(),
+                _ => Value::String(scope.expand(&string)),
+            }
+        }
+        Value::Sequence(items) => {
+            let mut substituted = Vec::new();
+            for item in items {
+                if let Some(Value::Sequence(spliced)) = item
+                    .as_str()
+                    .and_then(parameter_reference)
+                    .and_then(|name| parameters.get(&name))
+                {
+                    substituted.extend(spliced.iter().cloned());
+                    continue;
+                }
+
+                match item {
+                    Value::Mapping(mapping)
+                        if mapping.len() == 1 && mapping.keys().all(is_conditional) =>
+                    {
+                        let (_, body) = mapping.into_iter().next().unwrap();
+                        match substitute(body, scope, parameters) {
+                            Value::Sequence(body) => substituted.extend(body),
+                            Value::Null => {}
+                            body => substituted.push(body),
+                        }
+                    }
+                    item => substituted.push(substitute(item, scope, parameters)),
+                }
+            }
+
+            Value::Sequence(substituted)
+        }
+        Value::Mapping(mapping) => {
+            let mut substituted = Mapping::new();
+            for (key, value) in mapping {
+                if is_conditional(&key) {
+                    if let Value::Mapping(body) = substitute(value, scope, parameters) {
+                        substituted.extend(body);
+                    }
+                } else {
+                    substituted.insert(key, substitute(value, scope, parameters));
+                }
+            }
+
+            Value::Mapping(substituted)
+        }
+        value => value,
+    }
+}
+
+fn template_expression(input: &str) -> Option<&str> {
+    input
+        .trim()
+        .strip_prefix("${{")?
+        .strip_suffix("}}")
+        .map(str::trim)
+}
+
+fn parameter_reference(input: &str) -> Option<String> {
+    template_expression(input)?
+        .strip_prefix("parameters.")
+        .map(str::to_lowercase)
+}
+
+fn is_conditional(key: &Value) -> bool {
+    key.as_str()
+        .and_then(template_expression)
+        .is_some_and(|expression| {
+            expression.starts_with("if ")
+                || expression.starts_with("elseif ")
+                || expression == "else"
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_path() {
+        let including_file =
+            Path::new("CustomerPortal/apis/modules/RentalModule/azure-pipelines.yml");
+
+        assert_eq!(
+            resolve_path(including_file, "../../templates/build-dotnet.yml"),
+            PathBuf::from("CustomerPortal/apis/templates/build-dotnet.yml")
+        );
+        assert_eq!(
+            resolve_path(including_file, "build.yml@self"),
+            PathBuf::from("CustomerPortal/apis/modules/RentalModule/build.yml")
+        );
+        assert_eq!(
+            resolve_path(including_file, "pipelines/build.yml@templates"),
+            PathBuf::from("pipelines/build.yml")
+        );
+        assert_eq!(
+            resolve_path(including_file, "/pipelines/build.yml"),
+            PathBuf::from("pipelines/build.yml")
+        );
+    }
+
+    #[test]
+    fn test_parse_definition() {
+        let template = r#"
+parameters:
+  - name: project
+    type: string
+  - name: publish
+    type: boolean
+    default: false
+  - name: extraSteps
+    type: stepList
+    default: []
+
+steps:
+  - task: DotNetCoreCLI@2
+    inputs:
+      command: build
+      projects: ${{ parameters.project }}
+  - ${{ if eq(parameters.publish, true) }}:
+      - script: dotnet publish ${{ parameters.project }}
+  - ${{ parameters.extraSteps }}
+"#;
+        let arguments: Value = serde_yaml::from_str(
+            r#"
+project: src/Api/Api.csproj
+publish: true
+extraSteps:
+  - script: dotnet test src/Api.Tests/Api.Tests.csproj
+"#,
+        )
+        .unwrap();
+
+        let definition = parse_definition(template, Some(&arguments)).unwrap();
+        let scripts = definition
+            .steps
+            .iter()
+            .map(|step| {
+                step.script.clone().or_else(|| {
+                    step.inputs
+                        .get("projects")
+                        .and_then(Value::as_str)
+                        .map(str::to_string)
+                })
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            scripts,
+            vec![
+                Some("src/Api/Api.csproj".to_string()),
+                Some("dotnet publish src/Api/Api.csproj".to_string()),
+                Some("dotnet test src/Api.Tests/Api.Tests.csproj".to_string()),
+            ]
+        );
+    }
+}
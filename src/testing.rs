@@ -0,0 +1,13 @@
+//! Helpers shared by the tests.
+
+use std::path::Path;
+
+/// Writes each of `files`, a path relative to `root` and its contents, creating directories as
+/// needed.
+pub(crate) fn write_tree(root: &Path, files: &[(&str, &str)]) {
+    for (path, contents) in files {
+        let path = root.join(path);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, contents).unwrap();
+    }
+}
@@ -0,0 +1,363 @@
+//! Writing path filters into the `trigger` and `pr` sections of a pipeline file. The file is
+//! edited line by line rather than re-serialized, so comments and formatting outside of the
+//! `paths` sections are kept as they are.
+
+use std::path::Path;
+
+use crate::{filter::PathFilter, Error, Result};
+
+/// Replaces the `paths` of the `trigger` and `pr` sections in `contents` with `filter`. A
+/// missing `trigger` section is added, a missing `pr` section is not, since PR triggers in YAML
+/// only apply to some repository types. Sections set to `none` are left alone, and branch lists
+/// are turned into `branches: include:` so that `paths` can be added next to them.
+pub fn apply_path_filter(file: &Path, contents: &str, filter: &PathFilter) -> Result<String> {
+    let mut editor = Editor {
+        file,
+        lines: contents.lines().map(str::to_string).collect(),
+        indent: detect_indent(contents),
+        sequence_indent: detect_sequence_indent(contents),
+    };
+
+    match editor.find_key(0, editor.lines.len(), 0, "trigger") {
+        Some(index) => editor.update_section(index, filter)?,
+        None => editor.insert_trigger(filter),
+    }
+    if let Some(index) = editor.find_key(0, editor.lines.len(), 0, "pr") {
+        editor.update_section(index, filter)?;
+    }
+
+    let newline = if contents.contains("\r\n") {
+        "\r\n"
+    } else {
+        "\n"
+    };
+    let mut output = editor.lines.join(newline);
+    if contents.ends_with('\n') {
+        output.push_str(newline);
+    }
+
+    Ok(output)
+}
+
+struct Editor<'a> {
+    file: &'a Path,
+    lines: Vec<String>,
+    /// The indentation of nested mappings in the file.
+    indent: usize,
+    /// How far `- ` items are indented relative to their key, either 0 or `indent`.
+    sequence_indent: usize,
+}
+
+impl Editor<'_> {
+    /// Finds `key:` at exactly `indent` among `lines[start..end]`.
+    fn find_key(&self, start: usize, end: usize, indent: usize, key: &str) -> Option<usize> {
+        (start..end).find(|&index| {
+            let line = &self.lines[index];
+            indentation(line) == indent
+                && line.trim_start().strip_prefix(key).is_some_and(|rest| {
+                    rest.starts_with(':')
+                        && rest[1..]
+                            .chars()
+                            .next()
+                            .is_none_or(|c| c.is_whitespace() || c == '#')
+                })
+        })
+    }
+
+    /// The index after the last content line of the block starting at `index`: the lines
+    /// following it that are indented further, or list items at the same indentation, without
+    /// trailing blank or comment lines. Comments inside the block can be indented any way.
+    fn block_end(&self, index: usize) -> usize {
+        let indent = indentation(&self.lines[index]);
+        let mut end = index + 1;
+        for (offset, line) in self.lines[index + 1..].iter().enumerate() {
+            if line.trim().is_empty() || line.trim_start().starts_with('#') {
+                continue;
+            }
+            if indentation(line) < indent
+                || indentation(line) == indent && !line.trim_start().starts_with("- ")
+            {
+                break;
+            }
+            end = index + 1 + offset + 1;
+        }
+
+        end
+    }
+
+    fn update_section(&mut self, index: usize, filter: &PathFilter) -> Result<()> {
+        let line = self.lines[index].clone();
+        let indent = indentation(&line);
+        let key = line
+            .trim_start()
+            .split(':')
+            .next()
+            .unwrap_or_default()
+            .to_string();
+        let value = strip_comment(line.split_once(':').map_or("", |(_, value)| value));
+        let end = self.block_end(index);
+
+        let first_child = self.lines[index + 1..end]
+            .iter()
+            .find(|line| !line.trim().is_empty() && !line.trim_start().starts_with('#'));
+
+        let branches = match (value, first_child) {
+            ("none", _) => return Ok(()),
+            ("", None) => Vec::new(),
+            ("", Some(child)) if child.trim_start().starts_with("- ") => self.lines[index + 1..end]
+                .iter()
+                .filter_map(|line| line.trim_start().strip_prefix("- "))
+                .map(|branch| strip_comment(branch).to_string())
+                .collect(),
+            ("", Some(child)) => {
+                // Already a mapping, only replace or add `paths`.
+                let child_indent = indentation(child);
+                let paths = path_lines(child_indent, self.indent, self.sequence_indent, filter);
+                match self.find_key(index + 1, end, child_indent, "paths") {
+                    Some(start) => {
+                        let paths_end = self.block_end(start);
+                        self.lines.splice(start..paths_end, paths);
+                    }
+                    None => {
+                        self.lines.splice(end..end, paths);
+                    }
+                }
+                return Ok(());
+            }
+            (value, _) if value.starts_with('[') && value.ends_with(']') => value
+                [1..value.len() - 1]
+                .split(',')
+                .map(|branch| branch.trim().to_string())
+                .filter(|branch| !branch.is_empty())
+                .collect(),
+            (value, _) if value.starts_with('{') => {
+                return Err(Error::ParseError {
+                    file: self.file.to_path_buf(),
+                    line: index + 1,
+                    message: format!("`{}` is a flow mapping, which cannot be updated", key),
+                });
+            }
+            (value, _) => vec![value.to_string()],
+        };
+
+        // A list of branches, rewrite the section as a mapping, keeping the comment after it.
+        let child_indent = indent + self.indent;
+        let comment = line.find(" #").map_or("", |index| &line[index..]);
+        let mut section = vec![format!("{}{}:{}", " ".repeat(indent), key, comment)];
+        if !branches.is_empty() {
+            section.push(format!("{}branches:", " ".repeat(child_indent)));
+            section.extend(list_lines(
+                child_indent + self.indent,
+                self.sequence_indent,
+                "include",
+                &branches,
+            ));
+        }
+        section.extend(path_lines(
+            child_indent,
+            self.indent,
+            self.sequence_indent,
+            filter,
+        ));
+        self.lines.splice(index..end, section);
+
+        Ok(())
+    }
+
+    /// Adds a `trigger` section before the first top-level key.
+    fn insert_trigger(&mut self, filter: &PathFilter) {
+        let index = self
+            .lines
+            .iter()
+            .position(|line| {
+                indentation(line) == 0 && !line.trim().is_empty() && !line.starts_with('#')
+            })
+            .unwrap_or(self.lines.len());
+
+        let mut section = vec!["trigger:".to_string()];
+        section.extend(path_lines(
+            self.indent,
+            self.indent,
+            self.sequence_indent,
+            filter,
+        ));
+        section.push(String::new());
+        self.lines.splice(index..index, section);
+    }
+}
+
+fn path_lines(
+    indent: usize,
+    step: usize,
+    sequence_indent: usize,
+    filter: &PathFilter,
+) -> Vec<String> {
+    let include = filter
+        .include
+        .iter()
+        .map(|path| yaml_path(path))
+        .collect::<Vec<_>>();
+    let exclude = filter
+        .exclude
+        .iter()
+        .map(|path| yaml_path(path))
+        .collect::<Vec<_>>();
+
+    let mut lines = vec![format!("{}paths:", " ".repeat(indent))];
+    lines.extend(list_lines(
+        indent + step,
+        sequence_indent,
+        "include",
+        &include,
+    ));
+    if !exclude.is_empty() {
+        lines.extend(list_lines(
+            indent + step,
+            sequence_indent,
+            "exclude",
+            &exclude,
+        ));
+    }
+
+    lines
+}
+
+fn list_lines(indent: usize, sequence_indent: usize, key: &str, items: &[String]) -> Vec<String> {
+    std::iter::once(format!("{}{}:", " ".repeat(indent), key))
+        .chain(
+            items
+                .iter()
+                .map(|item| format!("{}- {}", " ".repeat(indent + sequence_indent), item)),
+        )
+        .collect()
+}
+
+/// YAML path filters are relative to the repository root without a leading `/`. Paths that
+/// would not be read back as a plain string are quoted.
+fn yaml_path(path: &str) -> String {
+    let path = path.trim_start_matches('/');
+    if path.starts_with([
+        '*', '!', '&', '[', '{', '\'', '"', '@', '`', '%', '|', '>', '?',
+    ]) || path.contains(": ")
+        || path.contains(" #")
+    {
+        format!("'{}'", path.replace('\'', "''"))
+    } else {
+        path.to_string()
+    }
+}
+
+fn indentation(line: &str) -> usize {
+    line.len() - line.trim_start_matches(' ').len()
+}
+
+fn strip_comment(value: &str) -> &str {
+    let value = value.trim();
+    match value.find(" #") {
+        Some(index) => value[..index].trim_end(),
+        None if value.starts_with('#') => "",
+        None => value,
+    }
+}
+
+/// The indentation of the first nested line in the file, 2 if there is none.
+fn detect_indent(contents: &str) -> usize {
+    contents
+        .lines()
+        .filter(|line| !line.trim().is_empty() && !line.trim_start().starts_with('#'))
+        .map(indentation)
+        .find(|&indent| indent > 0)
+        .unwrap_or(2)
+}
+
+/// Whether list items in the file are indented below their key (`key:\n  - item`) or written
+/// at the same level (`key:\n- item`).
+fn detect_sequence_indent(contents: &str) -> usize {
+    let lines = contents
+        .lines()
+        .filter(|line| !line.trim().is_empty() && !line.trim_start().starts_with('#'))
+        .collect::<Vec<_>>();
+
+    lines
+        .windows(2)
+        .find(|pair| pair[0].trim_end().ends_with(':') && pair[1].trim_start().starts_with("- "))
+        .map_or(detect_indent(contents), |pair| {
+            indentation(pair[1]).saturating_sub(indentation(pair[0]))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_apply_path_filter() {
+        let filter = PathFilter::parse("!/apis/modules/*; /apis/Shared/*; /pipelines/build.yml;");
+        let file = Path::new("azure-pipelines.yml");
+
+        let input = r#"# Builds the rental module
+trigger:
+  batch: true # one build at a time
+  branches:
+    include:
+    - main
+  paths:
+    include:
+    - apis/Old/*
+
+pr: [main, release/*]
+
+stages:
+- stage: Build # the only stage
+"#;
+        assert_eq!(
+            apply_path_filter(file, input, &filter).unwrap(),
+            r#"# Builds the rental module
+trigger:
+  batch: true # one build at a time
+  branches:
+    include:
+    - main
+  paths:
+    include:
+    - apis/Shared/*
+    - pipelines/build.yml
+    exclude:
+    - apis/modules/*
+
+pr:
+  branches:
+    include:
+    - main
+    - release/*
+  paths:
+    include:
+    - apis/Shared/*
+    - pipelines/build.yml
+    exclude:
+    - apis/modules/*
+
+stages:
+- stage: Build # the only stage
+"#
+        );
+
+        let input = "trigger:\n  paths:\n    include:\n# old layout\n    - apis/Old/*\n# stages\nsteps:\n- script: dotnet build\n";
+        assert_eq!(
+            apply_path_filter(file, input, &filter).unwrap(),
+            "trigger:\n  paths:\n    include:\n    - apis/Shared/*\n    - pipelines/build.yml\n    exclude:\n    - apis/modules/*\n# stages\nsteps:\n- script: dotnet build\n"
+        );
+
+        let input = "trigger:\n- main\n\nsteps:\n- script: dotnet build\n";
+        assert_eq!(
+            apply_path_filter(file, input, &filter).unwrap(),
+            "trigger:\n  branches:\n    include:\n    - main\n  paths:\n    include:\n    - apis/Shared/*\n    - pipelines/build.yml\n    exclude:\n    - apis/modules/*\n\nsteps:\n- script: dotnet build\n"
+        );
+
+        let input = "pr: none\r\nsteps:\r\n    - script: dotnet build\r\n";
+        assert_eq!(
+            apply_path_filter(file, input, &filter).unwrap(),
+            "trigger:\r\n    paths:\r\n        include:\r\n            - apis/Shared/*\r\n            - pipelines/build.yml\r\n        exclude:\r\n            - apis/modules/*\r\n\r\npr: none\r\nsteps:\r\n    - script: dotnet build\r\n"
+        );
+    }
+}
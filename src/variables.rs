@@ -0,0 +1,196 @@
+//! Expansion of Azure Pipelines macros (`$(name)`), template expressions (`${{ variables.name }}`,
+//! `${{ parameters.name }}`) and runtime expressions (`$[ variables.name ]`).
+
+use std::collections::HashMap;
+
+use serde_yaml::Value;
+
+use crate::{
+    definition::{parameter_value, Variables},
+    fenced,
+};
+
+/// Predefined variables that point at the checked out repository. Paths are relative to the
+/// repository root, so they expand to the current directory.
+const SOURCE_DIRECTORY_VARIABLES: [&str; 3] = [
+    "build.sourcesdirectory",
+    "build.repository.localpath",
+    "system.defaultworkingdirectory",
+];
+
+/// Upper bound on nested expansions, so variables that reference each other cannot loop forever.
+const MAX_EXPANSION_DEPTH: usize = 16;
+
+/// The variables and parameters visible at one level of a pipeline. Inner levels (stages, jobs)
+/// are created with [`Scope::with_variables`] and shadow the outer ones.
+#[derive(Debug, Clone)]
+pub struct Scope {
+    variables: HashMap<String, String>,
+    parameters: HashMap<String, String>,
+}
+
+impl Default for Scope {
+    fn default() -> Self {
+        Self {
+            variables: SOURCE_DIRECTORY_VARIABLES
+                .iter()
+                .map(|name| (name.to_string(), ".".to_string()))
+                .collect(),
+            parameters: HashMap::new(),
+        }
+    }
+}
+
+impl Scope {
+    pub fn with_parameters(&self, parameters: impl IntoIterator<Item = (String, Value)>) -> Self {
+        let mut scope = self.clone();
+        for (name, value) in parameters {
+            if let Some(value) = parameter_value(&value) {
+                scope.parameters.insert(name.to_lowercase(), value);
+            }
+        }
+
+        scope
+    }
+
+    pub fn with_variables(&self, variables: Option<&Variables>) -> Self {
+        let mut scope = self.clone();
+        for (name, value) in variables.map(Variables::values).unwrap_or_default() {
+            scope.set_variable(&name, value);
+        }
+
+        scope
+    }
+
+    pub fn set_variable(&mut self, name: &str, value: String) {
+        self.variables.insert(name.to_lowercase(), value);
+    }
+
+    /// Expands every variable and expression in `input` until nothing more can be resolved.
+    /// Unknown variables are left untouched.
+    pub fn expand(&self, input: &str) -> String {
+        let mut expanded = input.to_string();
+        for _ in 0..MAX_EXPANSION_DEPTH {
+            let next = self.expand_once(&expanded);
+            if next == expanded {
+                break;
+            }
+            expanded = next;
+        }
+
+        expanded
+    }
+
+    fn expand_once(&self, input: &str) -> String {
+        let mut expanded = String::new();
+        let mut rest = input;
+        while let Some(start) = rest.find('$') {
+            expanded.push_str(&rest[..start]);
+            let candidate = &rest[start..];
+
+            let (open, close) = if candidate.starts_with("${{") {
+                ("${{", "}}")
+            } else if candidate.starts_with("$[") {
+                ("$[", "]")
+            } else if candidate.starts_with("$(") {
+                ("$(", ")")
+            } else {
+                expanded.push('$');
+                rest = &candidate[1..];
+                continue;
+            };
+
+            let Ok((remaining, inner)) = fenced(open, close)(candidate) else {
+                expanded.push_str(candidate);
+                return expanded;
+            };
+
+            let value = if open == "$(" {
+                self.variable(inner.trim())
+            } else {
+                self.expression(inner.trim())
+            };
+            match value {
+                Some(value) => expanded.push_str(value),
+                None => expanded.push_str(&candidate[..candidate.len() - remaining.len()]),
+            }
+            rest = remaining;
+        }
+        expanded.push_str(rest);
+
+        expanded
+    }
+
+    fn variable(&self, name: &str) -> Option<&str> {
+        self.variables.get(&name.to_lowercase()).map(String::as_str)
+    }
+
+    /// Resolves `variables.name`, `variables['name']` and the `parameters` equivalents. Anything
+    /// more involved, such as function calls, is not evaluated.
+    fn expression(&self, expression: &str) -> Option<&str> {
+        let (kind, name) = match expression.split_once('[') {
+            Some((kind, name)) => (
+                kind.trim(),
+                name.strip_suffix(']')?.trim().trim_matches(['\'', '"']),
+            ),
+            None => expression.split_once('.')?,
+        };
+        if !name
+            .chars()
+            .all(|c| c.is_alphanumeric() || matches!(c, '.' | '_' | '-'))
+        {
+            return None;
+        }
+
+        match kind {
+            "variables" => self.variable(name),
+            "parameters" => self
+                .parameters
+                .get(&name.to_lowercase())
+                .map(String::as_str),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expand() {
+        let variables: Variables = serde_yaml::from_str(
+            r#"
+modulePath: "CustomerPortal/apis/modules/$(moduleName)"
+moduleName: RentalModule
+projectPath: "$(modulePath)/RentalModule.Api/RentalModule.Api.csproj"
+"#,
+        )
+        .unwrap();
+        let job_variables: Variables = serde_yaml::from_str(
+            r#"
+- name: moduleName
+  value: ${{ parameters.module }}
+"#,
+        )
+        .unwrap();
+
+        let scope = Scope::default()
+            .with_parameters([("module".to_string(), Value::from("OrderModule"))])
+            .with_variables(Some(&variables));
+        assert_eq!(
+            scope.expand("$(projectPath)"),
+            "CustomerPortal/apis/modules/RentalModule/RentalModule.Api/RentalModule.Api.csproj"
+        );
+        assert_eq!(
+            scope
+                .with_variables(Some(&job_variables))
+                .expand("$(Build.SourcesDirectory)/${{ variables['modulePath'] }}/$(unknown)"),
+            "./CustomerPortal/apis/modules/OrderModule/$(unknown)"
+        );
+        assert_eq!(
+            scope.expand("${{ eq(parameters.module, 'x') }}"),
+            "${{ eq(parameters.module, 'x') }}"
+        );
+    }
+}